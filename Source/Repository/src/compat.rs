@@ -0,0 +1,319 @@
+use crate::game::parse_game_version_req;
+use crate::index::VersionData;
+use bitcode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The release channel of a package version.
+/// Users opt into a channel and get all versions of that channel and the more stable ones.
+#[derive(
+	Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Encode, Decode,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+	#[default]
+	Stable,
+	Beta,
+}
+
+impl Channel {
+	/// The channel of a version without explicit channel, pre-releases are beta versions.
+	pub fn of_version(version: &semver::Version) -> Self {
+		if version.pre.is_empty() {
+			Channel::Stable
+		} else {
+			Channel::Beta
+		}
+	}
+}
+
+impl Display for Channel {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Channel::Stable => f.write_str("stable"),
+			Channel::Beta => f.write_str("beta"),
+		}
+	}
+}
+
+impl FromStr for Channel {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"stable" => Ok(Channel::Stable),
+			"beta" => Ok(Channel::Beta),
+			_ => Err(format!("unknown channel '{s}', expected 'stable' or 'beta'")),
+		}
+	}
+}
+
+/// The environment a package has to be compatible with,
+/// like the FIN and game version of a user and the mods they have installed.
+#[derive(Clone, Default)]
+pub struct QueryVersions {
+	pub fin_version: Option<semver::Version>,
+	pub game_version: Option<semver::Version>,
+	pub check_mods: bool,
+	pub mods: HashMap<String, Option<semver::Version>>,
+	pub channel: Channel,
+}
+
+/// The reason why a package version is not compatible with a [QueryVersions].
+#[derive(Clone, Debug)]
+pub enum Incompatibility {
+	Yanked(Option<String>),
+	Channel(Channel),
+	FinVersion(String),
+	GameVersion(String),
+	MissingMod(String),
+	ModVersion { id: String, requirement: String },
+}
+
+impl Display for Incompatibility {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Incompatibility::Yanked(None) => write!(f, "is yanked"),
+			Incompatibility::Yanked(Some(reason)) => write!(f, "is yanked: {reason}"),
+			Incompatibility::Channel(channel) => write!(f, "is in the {channel} channel"),
+			Incompatibility::FinVersion(req) => write!(f, "requires FIN version '{req}'"),
+			Incompatibility::GameVersion(req) => write!(f, "requires game version '{req}'"),
+			Incompatibility::MissingMod(id) => write!(f, "requires mod '{id}'"),
+			Incompatibility::ModVersion { id, requirement } => {
+				write!(f, "requires mod '{id}' with version '{requirement}'")
+			}
+		}
+	}
+}
+
+/// Like [semver::VersionReq::matches], but pre-releases also match the requirement
+/// of their release if the user opted into the beta channel.
+/// Only used for package versions, the FIN, game and mod versions are matched as they are.
+pub fn matches_channel(
+	requirement: &semver::VersionReq,
+	version: &semver::Version,
+	channel: Channel,
+) -> bool {
+	if requirement.matches(version) {
+		return true;
+	}
+	if channel < Channel::Beta || version.pre.is_empty() {
+		return false;
+	}
+	let mut release = version.clone();
+	release.pre = semver::Prerelease::EMPTY;
+	requirement.matches(&release)
+}
+
+/// Whether the version matches the requirement, [None] if either is missing or the requirement is invalid.
+pub fn check_version(requirement: Option<&String>, version: Option<&semver::Version>) -> Option<bool> {
+	let requirement = semver::VersionReq::parse(requirement?).ok()?;
+	Some(requirement.matches(version?))
+}
+
+/// Returns why the version is not compatible with the query, if it is not.
+/// The channel of the query only decides which package versions are offered,
+/// the FIN, game and mod versions of the user have to match the requirements as they are.
+pub fn incompatibility(query: &QueryVersions, version_data: &VersionData) -> Option<Incompatibility> {
+	if version_data.yanked {
+		return Some(Incompatibility::Yanked(version_data.yanked_reason.clone()));
+	}
+	if version_data.channel > query.channel {
+		return Some(Incompatibility::Channel(version_data.channel));
+	}
+	if !check_version(version_data.fin_version.as_ref(), query.fin_version.as_ref()).unwrap_or(true) {
+		return Some(Incompatibility::FinVersion(version_data.fin_version.clone()?));
+	}
+	let game_requirement = version_data
+		.game_version
+		.as_deref()
+		.and_then(|r| parse_game_version_req(r).ok());
+	if let Some((requirement, version)) = game_requirement.zip(query.game_version.as_ref()) {
+		if !requirement.matches(version) {
+			return Some(Incompatibility::GameVersion(requirement.to_string()));
+		}
+	}
+	if query.check_mods {
+		for m in &version_data.mod_dependencies {
+			let Some(version) = query.mods.get(&m.id) else {
+				return Some(Incompatibility::MissingMod(m.id.clone()));
+			};
+			if let Some(requirement) = &m.version {
+				let matches = semver::VersionReq::parse(requirement)
+					.ok()
+					.zip(version.as_ref())
+					.map(|(requirement, version)| requirement.matches(version))
+					.unwrap_or(false);
+				if !matches {
+					return Some(Incompatibility::ModVersion {
+						id: m.id.clone(),
+						requirement: requirement.clone(),
+					});
+				}
+			}
+		}
+	}
+	None
+}
+
+pub fn check_versions(query: &QueryVersions, version_data: &VersionData) -> Option<()> {
+	incompatibility(query, version_data).is_none().then_some(())
+}
+
+/// Returns the newest version that is compatible with the given [QueryVersions].
+pub fn latest_compatible_version<'a>(
+	query: &QueryVersions,
+	versions: &'a [(semver::Version, VersionData)],
+) -> Option<&'a semver::Version> {
+	versions
+		.iter()
+		.filter(|(_, data)| check_versions(query, data).is_some())
+		.map(|(version, _)| version)
+		.max()
+}
+
+/// Whether an installed package version has an update and if it got yanked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateCheck {
+	/// The newest version compatible with the [QueryVersions], [None] if there is none.
+	pub latest: Option<semver::Version>,
+	/// Whether the latest version is newer than the installed one.
+	pub upgrade: bool,
+	pub yanked: bool,
+	pub yanked_reason: Option<String>,
+}
+
+/// Checks the installed version against all versions of its package.
+pub fn check_update(
+	query: &QueryVersions,
+	installed: &semver::Version,
+	versions: &[(semver::Version, VersionData)],
+) -> UpdateCheck {
+	let latest = latest_compatible_version(query, versions).cloned();
+	let installed_data = versions
+		.iter()
+		.find(|(v, _)| v == installed)
+		.map(|(_, data)| data);
+	UpdateCheck {
+		upgrade: latest.as_ref().is_some_and(|latest| latest > installed),
+		latest,
+		yanked: installed_data.is_some_and(|data| data.yanked),
+		yanked_reason: installed_data.and_then(|data| data.yanked_reason.clone()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn data(fin_version: &str, channel: Channel) -> VersionData {
+		VersionData {
+			fin_version: Some(fin_version.to_string()),
+			channel,
+			..Default::default()
+		}
+	}
+
+	fn versions() -> Vec<(semver::Version, VersionData)> {
+		vec![
+			(semver::Version::new(1, 0, 0), data(">=0.3.0", Channel::Stable)),
+			(semver::Version::new(1, 1, 0), data(">=0.4.0", Channel::Stable)),
+			(semver::Version::parse("1.2.0-beta.1").unwrap(), data(">=0.4.0", Channel::Beta)),
+		]
+	}
+
+	fn query(fin_version: &str, channel: Channel) -> QueryVersions {
+		QueryVersions {
+			fin_version: Some(semver::Version::parse(fin_version).unwrap()),
+			channel,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn filters_by_channel() {
+		let beta = data(">=0.3.0", Channel::Beta);
+		assert!(matches!(
+			incompatibility(&query("0.4.0", Channel::Stable), &beta),
+			Some(Incompatibility::Channel(Channel::Beta))
+		));
+		assert!(incompatibility(&query("0.4.0", Channel::Beta), &beta).is_none());
+		assert!(incompatibility(&query("0.4.0", Channel::Beta), &data(">=0.3.0", Channel::Stable)).is_none());
+	}
+
+	#[test]
+	fn channel_does_not_relax_environment_requirements() {
+		let stable = data(">=0.4.0", Channel::Stable);
+		assert!(matches!(
+			incompatibility(&query("0.4.0-beta.1", Channel::Beta), &stable),
+			Some(Incompatibility::FinVersion(_))
+		));
+
+		let mut mods = HashMap::new();
+		mods.insert("SML".to_string(), Some(semver::Version::parse("3.8.0-rc.1").unwrap()));
+		let query = QueryVersions {
+			check_mods: true,
+			mods,
+			channel: Channel::Beta,
+			..Default::default()
+		};
+		let data = VersionData {
+			mod_dependencies: vec![crate::index::ModDependency {
+				id: "SML".to_string(),
+				version: Some(">=3.8.0".to_string()),
+			}],
+			..Default::default()
+		};
+		assert!(matches!(incompatibility(&query, &data), Some(Incompatibility::ModVersion { .. })));
+	}
+
+	#[test]
+	fn selects_latest_compatible_version() {
+		let versions = versions();
+		let latest = |fin_version, channel| latest_compatible_version(&query(fin_version, channel), &versions).cloned();
+		assert_eq!(latest("0.3.19", Channel::Stable), Some(semver::Version::new(1, 0, 0)));
+		assert_eq!(latest("0.4.0", Channel::Stable), Some(semver::Version::new(1, 1, 0)));
+		assert_eq!(latest("0.4.0", Channel::Beta), Some(semver::Version::parse("1.2.0-beta.1").unwrap()));
+		assert_eq!(latest("0.2.0", Channel::Beta), None);
+
+		let mut versions = versions;
+		versions[1].1.yanked = true;
+		assert_eq!(
+			latest_compatible_version(&query("0.4.0", Channel::Stable), &versions),
+			Some(&semver::Version::new(1, 0, 0))
+		);
+	}
+
+	#[test]
+	fn checks_updates() {
+		let mut versions = versions();
+		versions[0].1.yanked = true;
+		versions[0].1.yanked_reason = Some("broken".to_string());
+		let stable = query("0.4.0", Channel::Stable);
+
+		let update = check_update(&stable, &semver::Version::new(1, 0, 0), &versions);
+		assert_eq!(
+			update,
+			UpdateCheck {
+				latest: Some(semver::Version::new(1, 1, 0)),
+				upgrade: true,
+				yanked: true,
+				yanked_reason: Some("broken".to_string()),
+			}
+		);
+
+		let update = check_update(&stable, &semver::Version::new(1, 1, 0), &versions);
+		assert!(!update.upgrade && !update.yanked);
+
+		let beta = semver::Version::parse("1.2.0-beta.1").unwrap();
+		let update = check_update(&stable, &beta, &versions);
+		assert_eq!(update.latest, Some(semver::Version::new(1, 1, 0)));
+		assert!(!update.upgrade);
+
+		let update = check_update(&query("0.2.0", Channel::Stable), &semver::Version::new(1, 1, 0), &versions);
+		assert_eq!(update.latest, None);
+		assert!(!update.upgrade);
+	}
+}
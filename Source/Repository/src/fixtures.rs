@@ -0,0 +1,119 @@
+//! Packages and indices shared by the tests of the modules.
+
+use crate::index::{add_packages_to_index, build_schema, PackageSchema};
+use crate::model::{Package, Readme, Version, EEPROM};
+use crate::tokenizer::register_tokenizers;
+use tantivy::{Index, Searcher};
+
+pub fn version(version: &str, metadata: &str) -> Version {
+	Version::from_metadata(
+		semver::Version::parse(version).unwrap(),
+		format!("v{version}"),
+		toml::from_str(metadata).unwrap(),
+	)
+	.unwrap()
+	.0
+}
+
+pub fn package(id: &str, tags: &[&str], authors: &[&str], versions: Vec<Version>) -> Package {
+	Package {
+		id: id.to_string(),
+		name: id.to_string(),
+		short_description: format!("The {id} package"),
+		readme: Readme::Markdown(String::new()),
+		tags: tags.iter().map(|t| t.to_string()).collect(),
+		authors: authors.iter().map(|a| a.to_string()).collect(),
+		version: versions.first().map(|v| v.version.clone()),
+		published: versions.iter().filter_map(|v| v.published).min(),
+		updated: versions.iter().filter_map(|v| v.published).max(),
+		versions,
+		deprecated: false,
+		replaced_by: None,
+	}
+}
+
+pub fn eeprom(name: &str, title: &str, code: &str) -> EEPROM {
+	EEPROM {
+		name: name.to_string(),
+		title: title.to_string(),
+		description: String::new(),
+		code: Some(code.to_string()),
+		requirements: Vec::new(),
+		minified: None,
+		size: None,
+		minified_size: None,
+	}
+}
+
+/// Three packages with versions, dependencies and EEPROMs, most tests search them.
+pub fn packages() -> Vec<Package> {
+	let mut packages = vec![
+		package(
+			"NetBoot",
+			&["boot", "network"],
+			&["Panakotta00"],
+			vec![
+				version(
+					"0.1.0",
+					"fin_version = \">=0.3.19\"\nprovides = [\"bootloader\"]\nreleased = 2024-01-01",
+				),
+				version(
+					"0.2.0",
+					"fin_version = \">=0.4.0\"\nprovides = [\"bootloader\"]\nreleased = 2024-06-01",
+				),
+			],
+		),
+		package(
+			"Clock",
+			&["utility"],
+			&["Panakotta00"],
+			vec![version(
+				"1.0.0",
+				"fin_version = \">=0.3.0\"\nreleased = 2024-03-01\npackage_dependencies = [{ id = \"NetBoot\" }]",
+			)],
+		),
+		package(
+			"PowerMonitor",
+			&["power", "utility"],
+			&["Someone"],
+			vec![version(
+				"0.0.1",
+				"fin_version = \">=0.3.19\"\nreleased = 2023-05-01T12:00:00Z\n\
+				mod_dependencies = [{ id = \"SML\", version = \">=3.6\" }]\n\
+				package_dependencies = [{ id = \"NetBoot\" }, { id = \"Clock\" }]",
+			)],
+		),
+	];
+	packages[1].readme = Readme::Markdown("Run the <Clock> installer".to_string());
+	packages[2].versions[0].eeprom = vec![EEPROM {
+		description: "Shows the grid".to_string(),
+		..eeprom(
+			"Monitor.lua",
+			"Monitor",
+			"local gpu = computer.getPCIDevices(classes.GPUT1)[1]\nlocal screen = component.findComponent(classes.Screen)[1]",
+		)
+	}];
+	packages[0].versions[0].eeprom = vec![eeprom("Display.lua", "Display", "local gpu = classes.GPUT2")];
+	packages[0].versions[1].eeprom = vec![eeprom(
+		"Client.lua",
+		"Client",
+		"local net = computer.getPCIDevices(classes.NetworkCard)[1]\nevent.listen(net)",
+	)];
+	packages
+}
+
+/// Indexes the packages like the indexer, with the requirements and minified code of their EEPROMs.
+pub fn index(mut packages: Vec<Package>) -> (Searcher, PackageSchema) {
+	let (schema, package_schema) = build_schema();
+	let index = Index::create_in_ram(schema);
+	register_tokenizers(&index);
+	let mut writer = index.writer(15_000_000).unwrap();
+	for eeprom in packages.iter_mut().flat_map(|p| &mut p.versions).flat_map(|v| &mut v.eeprom) {
+		let ast = crate::lua::parse(eeprom.code.as_deref().unwrap()).unwrap();
+		eeprom.requirements = crate::lua::requirements(&ast);
+		eeprom.minified = Some(crate::lua::minify(&ast));
+	}
+	add_packages_to_index(&writer, &package_schema, packages).unwrap();
+	writer.commit().unwrap();
+	(index.reader().unwrap().searcher(), package_schema)
+}
@@ -0,0 +1,217 @@
+use crate::util::{parse_version_lenient, parse_version_req_lenient};
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// A Satisfactory release from the bundled game version table.
+#[derive(Clone)]
+pub struct GameRelease {
+	pub name: String,
+	pub aliases: Vec<String>,
+	pub version: semver::Version,
+	/// The build number (changelist) of the first public build of this release.
+	pub first_build: u64,
+}
+
+#[derive(Deserialize)]
+struct GameReleaseTable {
+	release: Vec<GameReleaseEntry>,
+}
+
+#[derive(Deserialize)]
+struct GameReleaseEntry {
+	name: String,
+	#[serde(default)]
+	aliases: Vec<String>,
+	version: String,
+	first_build: u64,
+}
+
+/// Returns all known releases, ordered by their first build.
+pub fn releases() -> &'static [GameRelease] {
+	static RELEASES: OnceLock<Vec<GameRelease>> = OnceLock::new();
+	RELEASES.get_or_init(|| {
+		let table: GameReleaseTable = toml::from_str(include_str!("../data/game_versions.toml"))
+			.expect("bundled game version table is invalid");
+		let mut releases: Vec<_> = table
+			.release
+			.into_iter()
+			.map(|r| GameRelease {
+				version: semver::Version::parse(&r.version)
+					.expect("bundled game version table contains invalid version"),
+				name: r.name,
+				aliases: r.aliases,
+				first_build: r.first_build,
+			})
+			.collect();
+		releases.sort_by_key(|r| r.first_build);
+		releases
+	})
+}
+
+fn normalize_name(s: &str) -> String {
+	s.chars()
+		.filter(|c| !c.is_whitespace())
+		.flat_map(|c| c.to_lowercase())
+		.collect()
+}
+
+pub fn release_by_name(name: &str) -> Option<&'static GameRelease> {
+	let name = normalize_name(name);
+	releases().iter().find(|r| {
+		normalize_name(&r.name) == name || r.aliases.iter().any(|a| normalize_name(a) == name)
+	})
+}
+
+/// Returns the newest release the given build belongs to.
+pub fn release_by_build(build: u64) -> Option<&'static GameRelease> {
+	releases().iter().rev().find(|r| r.first_build <= build)
+}
+
+/// Parses build numbers like `264901`, `Build 264901` or `CL#264901`.
+/// Plain numbers need at least 5 digits so they can't be confused with a version.
+pub fn parse_build(s: &str) -> Option<u64> {
+	let s = normalize_name(s);
+	let number = ["build", "changelist", "cl"]
+		.iter()
+		.find_map(|prefix| s.strip_prefix(prefix))
+		.map(|s| s.trim_start_matches(['#', ':']));
+	let number = match number {
+		Some(number) => number,
+		None if s.len() >= 5 => s.as_str(),
+		None => return None,
+	};
+	if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+		return None;
+	}
+	number.parse().ok()
+}
+
+fn resolve_release(s: &str) -> Option<&'static GameRelease> {
+	match parse_build(s) {
+		Some(build) => release_by_build(build),
+		None => release_by_name(s),
+	}
+}
+
+/// Parses a game version entered by a user into its semver form.
+/// Accepts build numbers, release names like `Update 8` or `U8`, and semver-ish versions like `0.8.3` or `1.0`.
+/// Build numbers and release names map to the version of their release, like `0.8.0`,
+/// as the release table has no patch releases. A requirement on a patch like `>=0.8.3`
+/// therefore never matches a build of that release, requirements should name the release like `>=0.8`.
+pub fn parse_game_version(s: &str) -> Result<semver::Version, String> {
+	let s = s.trim();
+	if let Some(release) = resolve_release(s) {
+		return Ok(release.version.clone());
+	}
+	if let Some(build) = parse_build(s) {
+		return Err(format!("build {build} is older than the first known Satisfactory release"));
+	}
+	parse_version_lenient(s).map(|v| v.value).map_err(|_| {
+		format!("'{s}' is neither a known Satisfactory release, a build number nor a version")
+	})
+}
+
+/// Parses a game version requirement into a semver requirement.
+/// Operands can be anything [parse_game_version] accepts, like `>= Update 8, < 1.0` or `>=264901`.
+/// Only build numbers and release names are replaced by the version of their release,
+/// numeric operands stay as written so `=1.0` still matches the patches of 1.0.
+pub fn parse_game_version_req(s: &str) -> Result<semver::VersionReq, String> {
+	let mut normalized = String::new();
+	let mut operand = String::new();
+	let flush = |operand: &mut String, normalized: &mut String| {
+		// a partial version like `1.0` keeps its meaning of `>=1.0.0, <1.1.0` and does not become a release
+		let numeric = operand
+			.trim()
+			.chars()
+			.all(|c| c.is_ascii_digit() || ".*xX".contains(c));
+		let release = match parse_build(operand.trim()) {
+			Some(build) => release_by_build(build),
+			None if numeric => None,
+			None => release_by_name(operand.trim()),
+		};
+		match release {
+			Some(release) => normalized.push_str(&format!(" {} ", release.version)),
+			None => normalized.push_str(operand),
+		}
+		operand.clear();
+	};
+	for c in s.chars() {
+		if ",<>=~^".contains(c) {
+			flush(&mut operand, &mut normalized);
+			normalized.push(c);
+		} else {
+			operand.push(c);
+		}
+	}
+	flush(&mut operand, &mut normalized);
+
+	parse_version_req_lenient(normalized.trim())
+		.map(|r| r.value)
+		.map_err(|e| format!("'{s}' is not a valid game version requirement: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn version(s: &str) -> String {
+		parse_game_version(s).unwrap().to_string()
+	}
+
+	#[test]
+	fn parses_build_numbers() {
+		assert_eq!(parse_build("264901"), Some(264901));
+		assert_eq!(parse_build("Build 264901"), Some(264901));
+		assert_eq!(parse_build("CL#264901"), Some(264901));
+		assert_eq!(parse_build("changelist: 365306"), Some(365306));
+		assert_eq!(parse_build("1.0"), None);
+		assert_eq!(parse_build("1234"), None);
+		assert_eq!(parse_build("build"), None);
+	}
+
+	#[test]
+	fn maps_builds_to_releases() {
+		assert_eq!(release_by_build(100000).map(|r| &r.name), None);
+		assert_eq!(release_by_build(152331).unwrap().name, "Update 5");
+		assert_eq!(release_by_build(264900).unwrap().name, "Update 7");
+		assert_eq!(release_by_build(264901).unwrap().name, "Update 8");
+		assert_eq!(release_by_build(380000).unwrap().name, "1.0");
+		assert_eq!(release_by_build(u64::MAX).unwrap().version, releases().last().unwrap().version);
+	}
+
+	#[test]
+	fn parses_game_versions() {
+		assert_eq!(version("Update 8"), "0.8.0");
+		assert_eq!(version("u8"), "0.8.0");
+		assert_eq!(version("Build 280000"), "0.8.0");
+		assert_eq!(version("1.0 Release"), "1.0.0");
+		assert_eq!(version("0.8.3"), "0.8.3");
+		assert_eq!(version("1.1"), "1.1.0");
+		assert!(parse_game_version("Update 42").is_err());
+		assert!(parse_game_version("").is_err());
+	}
+
+	#[test]
+	fn rejects_builds_before_the_first_release() {
+		assert!(parse_game_version("12345").is_err());
+		assert!(parse_game_version("Build 100000").is_err());
+	}
+
+	#[test]
+	fn parses_game_version_requirements() {
+		let requirement = |s| parse_game_version_req(s).unwrap().to_string();
+		assert_eq!(requirement(">= Update 8, < 1.0"), ">=0.8.0, <1.0");
+		assert_eq!(requirement(">=264901"), ">=0.8.0");
+		assert_eq!(requirement("^1.0"), "^1.0");
+		assert_eq!(requirement(">= 1.0 Release"), ">=1.0.0");
+		assert_eq!(requirement(">=0.8.3"), ">=0.8.3");
+		assert!(parse_game_version_req(">= Update 42").is_err());
+	}
+
+	#[test]
+	fn partial_requirements_match_patches() {
+		let requirement = parse_game_version_req("=1.0").unwrap();
+		assert!(requirement.matches(&semver::Version::new(1, 0, 1)));
+		assert!(!requirement.matches(&semver::Version::new(1, 1, 0)));
+	}
+}
@@ -1,46 +1,181 @@
+use crate::compat::Channel;
+use crate::lua::{EepromSize, LintMessage};
 use crate::model::Package;
+use crate::tokenizer::{ENGLISH_TOKENIZER, IDENTIFIER_TOKENIZER};
+use crate::util::version_req_lower_bound;
 use bitcode::{Decode, Encode};
+use tantivy::collector::TopDocs;
 use tantivy::doc;
+use tantivy::query::{BooleanQuery, Occur, Query, TermQuery};
 use tantivy::schema::*;
-use tantivy::{Index, IndexWriter};
+use tantivy::{DateTime, IndexWriter, Searcher, Term};
 
 #[derive(Clone)]
 pub struct PackageSchema {
 	pub id: Field,
+	/// The id split into words, to match `PowerMonitor` with `power monitor`.
+	pub id_text: Field,
 	pub name: Field,
+	/// Stored to highlight the matches of a search.
 	pub short_description: Field,
+	/// Stored to highlight the matches of a search.
 	pub readme: Field,
+	/// Stored to find related packages.
 	pub tags: Field,
 	pub authors: Field,
 	pub versions: Field,
 	pub version_data: Field,
+	pub provides: Field,
+	pub deprecated: Field,
+	pub replaced_by: Field,
+	/// Facets of the package: `/tags/<tag>`, `/authors/<author>`, `/mods/<mod id>`
+	/// and `/fin/<major.minor>` for the minimum FIN versions of its versions.
+	pub facets: Field,
+	pub published: Field,
+	pub updated: Field,
+	/// The position of the package when sorted by name, to sort results by name with a fast field.
+	pub name_rank: Field,
+	/// The number of other packages depending on this package.
+	pub dependents: Field,
+	/// The words of the id, name, tags and description, as dictionary for typo tolerant search and spelling suggestions.
+	pub spelling: Field,
+	/// Autocompletion entries of the package name, tags and authors, see [completion_terms].
+	pub completion: Field,
+	/// Whether the document is a package or one of its EEPROMs, [KIND_PACKAGE] or [KIND_EEPROM].
+	pub kind: Field,
+	/// The id of the package an EEPROM document belongs to.
+	pub eeprom_package: Field,
+	/// The version of the package an EEPROM document belongs to, its [VersionData] is in `version_data`.
+	pub eeprom_version: Field,
+	/// The file name of the EEPROM in the version folder.
+	pub eeprom_file: Field,
+	pub eeprom_title: Field,
+	pub eeprom_description: Field,
+	/// The Lua source of the EEPROM, with identifiers like `findComponent` split into words.
+	pub eeprom_code: Field,
+	/// The FIN APIs and classes the EEPROM uses, see [crate::lua::requirements].
+	pub eeprom_requirements: Field,
+	/// The code of the EEPROM minified by [crate::lua::minify], only stored.
+	pub eeprom_minified: Field,
+	/// The requirements of the EEPROMs of the latest version of a package, see [latest_version],
+	/// to search like `requires:InternetCard`.
+	pub requires: Field,
 }
 
-#[derive(Encode, Decode)]
+/// Builds the completion terms of a value, one per word, so prefixes of any word match.
+/// The terms have the form `{lowercase text from the word}\0{kind}\0{value}\0{label}`.
+pub fn completion_terms(kind: &str, value: &str, label: &str) -> Vec<String> {
+	let lowercase = label.to_lowercase();
+	let mut starts = vec![0];
+	starts.extend(
+		lowercase
+			.char_indices()
+			.zip(lowercase.chars().skip(1))
+			.filter(|((_, c), next)| !c.is_alphanumeric() && next.is_alphanumeric())
+			.map(|((i, c), _)| i + c.len_utf8()),
+	);
+	starts
+		.into_iter()
+		.map(|start| format!("{}\0{kind}\0{value}\0{label}", &lowercase[start..]))
+		.collect()
+}
+
+/// The facet roots of [PackageSchema::facets].
+pub const FACET_TAGS: &str = "tags";
+pub const FACET_AUTHORS: &str = "authors";
+pub const FACET_MODS: &str = "mods";
+pub const FACET_FIN: &str = "fin";
+
+/// The kinds of documents, see [PackageSchema::kind].
+pub const KIND_PACKAGE: &str = "package";
+pub const KIND_EEPROM: &str = "eeprom";
+
+/// The kinds of [PackageSchema::completion] entries.
+pub const COMPLETION_PACKAGE: &str = "package";
+pub const COMPLETION_TAG: &str = "tag";
+pub const COMPLETION_AUTHOR: &str = "author";
+
+#[derive(Clone, Default, Encode, Decode)]
 pub struct VersionData {
+	pub folder: String,
 	pub fin_version: Option<String>,
 	pub game_version: Option<String>,
 	pub mod_dependencies: Vec<ModDependency>,
+	pub package_dependencies: Vec<PackageDependency>,
+	pub provides: Vec<String>,
+	pub conflicts: Vec<String>,
+	pub channel: Channel,
+	pub yanked: bool,
+	pub yanked_reason: Option<String>,
+	pub lint: Vec<LintMessage>,
+	pub eeprom_sizes: Vec<EepromSize>,
 }
 
-#[derive(Encode, Decode)]
+#[derive(Clone, Encode, Decode)]
 pub struct ModDependency {
 	pub id: String,
 	pub version: Option<String>,
 }
 
+#[derive(Clone, Encode, Decode)]
+pub struct PackageDependency {
+	pub id: String,
+	pub version: Option<String>,
+}
+
+/// Options of a text field analyzed by the given tokenizer, see [crate::tokenizer::register_tokenizers].
+fn text_options(tokenizer: &str) -> TextOptions {
+	TextOptions::default().set_indexing_options(
+		TextFieldIndexing::default()
+			.set_tokenizer(tokenizer)
+			.set_index_option(IndexRecordOption::WithFreqsAndPositions),
+	)
+}
+
 pub fn build_schema() -> (Schema, PackageSchema) {
 	let mut builder = Schema::builder();
 
 	let package = PackageSchema {
 		id: builder.add_text_field("id", STRING | STORED | FAST),
-		name: builder.add_text_field("name", TEXT),
-		short_description: builder.add_text_field("short_description", TEXT),
-		readme: builder.add_text_field("readme", TEXT),
-		tags: builder.add_text_field("tags", STRING),
+		id_text: builder.add_text_field("id_text", text_options(IDENTIFIER_TOKENIZER)),
+		name: builder.add_text_field("name", text_options(IDENTIFIER_TOKENIZER)),
+		short_description: builder.add_text_field(
+			"short_description",
+			text_options(ENGLISH_TOKENIZER).set_stored(),
+		),
+		readme: builder.add_text_field("readme", text_options(ENGLISH_TOKENIZER).set_stored()),
+		tags: builder.add_text_field("tags", STRING | STORED),
 		authors: builder.add_text_field("authors", STRING),
 		versions: builder.add_text_field("versions", STRING | STORED),
 		version_data: builder.add_bytes_field("version_data", FAST | STORED),
+		provides: builder.add_text_field("provides", STRING | STORED),
+		deprecated: builder.add_bool_field("deprecated", INDEXED | STORED | FAST),
+		replaced_by: builder.add_text_field("replaced_by", STRING | STORED),
+		facets: builder.add_facet_field("facets", FacetOptions::default()),
+		published: builder.add_date_field("published", INDEXED | STORED | FAST),
+		updated: builder.add_date_field("updated", INDEXED | STORED | FAST),
+		name_rank: builder.add_u64_field("name_rank", FAST),
+		dependents: builder.add_u64_field("dependents", STORED | FAST),
+		spelling: builder.add_text_field("spelling", text_options(IDENTIFIER_TOKENIZER)),
+		completion: builder.add_text_field("completion", STRING),
+		kind: builder.add_text_field("kind", STRING),
+		eeprom_package: builder.add_text_field("eeprom_package", STRING | STORED),
+		eeprom_version: builder.add_text_field("eeprom_version", STRING | STORED),
+		eeprom_file: builder.add_text_field("eeprom_file", STRING | STORED),
+		eeprom_title: builder
+			.add_text_field("eeprom_title", text_options(IDENTIFIER_TOKENIZER).set_stored()),
+		eeprom_description: builder.add_text_field(
+			"eeprom_description",
+			text_options(ENGLISH_TOKENIZER).set_stored(),
+		),
+		eeprom_code: builder
+			.add_text_field("eeprom_code", text_options(IDENTIFIER_TOKENIZER).set_stored()),
+		eeprom_requirements: builder.add_text_field(
+			"eeprom_requirements",
+			text_options(IDENTIFIER_TOKENIZER).set_stored(),
+		),
+		eeprom_minified: builder.add_text_field("eeprom_minified", STORED),
+		requires: builder.add_text_field("requires", text_options(IDENTIFIER_TOKENIZER)),
 	};
 
 	(builder.build(), package)
@@ -49,6 +184,7 @@ pub fn build_schema() -> (Schema, PackageSchema) {
 pub fn load_schema(schema: &Schema) -> tantivy::Result<PackageSchema> {
 	Ok(PackageSchema {
 		id: schema.get_field("id")?,
+		id_text: schema.get_field("id_text")?,
 		name: schema.get_field("name")?,
 		short_description: schema.get_field("short_description")?,
 		readme: schema.get_field("readme")?,
@@ -56,31 +192,178 @@ pub fn load_schema(schema: &Schema) -> tantivy::Result<PackageSchema> {
 		authors: schema.get_field("authors")?,
 		versions: schema.get_field("versions")?,
 		version_data: schema.get_field("version_data")?,
+		provides: schema.get_field("provides")?,
+		deprecated: schema.get_field("deprecated")?,
+		replaced_by: schema.get_field("replaced_by")?,
+		facets: schema.get_field("facets")?,
+		published: schema.get_field("published")?,
+		updated: schema.get_field("updated")?,
+		name_rank: schema.get_field("name_rank")?,
+		dependents: schema.get_field("dependents")?,
+		spelling: schema.get_field("spelling")?,
+		completion: schema.get_field("completion")?,
+		kind: schema.get_field("kind")?,
+		eeprom_package: schema.get_field("eeprom_package")?,
+		eeprom_version: schema.get_field("eeprom_version")?,
+		eeprom_file: schema.get_field("eeprom_file")?,
+		eeprom_title: schema.get_field("eeprom_title")?,
+		eeprom_description: schema.get_field("eeprom_description")?,
+		eeprom_code: schema.get_field("eeprom_code")?,
+		eeprom_requirements: schema.get_field("eeprom_requirements")?,
+		eeprom_minified: schema.get_field("eeprom_minified")?,
+		requires: schema.get_field("requires")?,
 	})
 }
 
+/// Statistics of a package in relation to all other packages of the index.
+#[derive(Clone, Default)]
+pub struct PackageStats {
+	pub name_rank: u64,
+	pub dependents: u64,
+}
+
+/// Adds all packages to the index, with the [PackageStats] computed over the given packages.
+pub fn add_packages_to_index(
+	index_writer: &IndexWriter<TantivyDocument>,
+	package_schema: &PackageSchema,
+	packages: Vec<Package>,
+) -> tantivy::Result<()> {
+	let mut names: Vec<_> = packages.iter().map(|p| p.name.to_lowercase()).collect();
+	names.sort();
+	for package in packages.iter() {
+		let stats = PackageStats {
+			name_rank: names.partition_point(|n| *n < package.name.to_lowercase()) as u64,
+			dependents: packages
+				.iter()
+				.filter(|p| p.id != package.id)
+				.filter(|p| {
+					p.versions
+						.iter()
+						.any(|v| v.package_dependencies.iter().any(|d| d.id == package.id))
+				})
+				.count() as u64,
+		};
+		add_package_to_index(index_writer, package_schema, package.clone(), &stats)?;
+	}
+	Ok(())
+}
+
+/// The version most users get, the newest stable version that is not yanked,
+/// falling back to the newest beta and then to the newest yanked version.
+fn latest_version(versions: &[crate::model::Version]) -> Option<&crate::model::Version> {
+	versions
+		.iter()
+		.max_by_key(|v| (!v.yanked, v.channel == Channel::Stable, &v.version))
+}
+
 pub fn add_package_to_index(
 	index_writer: &IndexWriter<TantivyDocument>,
 	package_schema: &PackageSchema,
 	package: Package,
+	stats: &PackageStats,
 ) -> tantivy::Result<tantivy::Opstamp> {
+	let package_id = package.id.clone();
+	let name_completions = completion_terms(COMPLETION_PACKAGE, &package.id, &package.name);
 	let mut doc: TantivyDocument = doc!(
+		package_schema.kind => KIND_PACKAGE,
+		package_schema.spelling => package.id.clone(),
+		package_schema.spelling => package.name.clone(),
+		package_schema.spelling => package.short_description.clone(),
+		package_schema.id_text => package.id.clone(),
 		package_schema.id => package.id,
 		package_schema.name => package.name,
 		package_schema.short_description => package.short_description,
 		package_schema.readme => package.readme.to_string(),
+		package_schema.deprecated => package.deprecated,
+		package_schema.name_rank => stats.name_rank,
+		package_schema.dependents => stats.dependents,
 	);
 
+	for term in name_completions {
+		doc.add_text(package_schema.completion, term);
+	}
+	for tag in &package.tags {
+		for term in completion_terms(COMPLETION_TAG, tag, tag) {
+			doc.add_text(package_schema.completion, term);
+		}
+	}
+	for author in &package.authors {
+		for term in completion_terms(COMPLETION_AUTHOR, author, author) {
+			doc.add_text(package_schema.completion, term);
+		}
+	}
+
+	if let Some(published) = package.published {
+		doc.add_date(package_schema.published, DateTime::from_timestamp_secs(published));
+	}
+	if let Some(updated) = package.updated {
+		doc.add_date(package_schema.updated, DateTime::from_timestamp_secs(updated));
+	}
+
+	if let Some(replaced_by) = &package.replaced_by {
+		doc.add_text(package_schema.replaced_by, replaced_by);
+	}
+
 	for tag in &package.tags {
 		doc.add_text(package_schema.tags, tag);
+		doc.add_text(package_schema.spelling, tag);
+		doc.add_facet(package_schema.facets, Facet::from_path([FACET_TAGS, tag]));
 	}
 
 	for author in &package.authors {
 		doc.add_text(package_schema.authors, author);
+		doc.add_facet(package_schema.facets, Facet::from_path([FACET_AUTHORS, author]));
+	}
+
+	let mut mods: Vec<&String> = package
+		.versions
+		.iter()
+		.flat_map(|v| v.mod_dependencies.iter().map(|m| &m.id))
+		.collect();
+	mods.sort();
+	mods.dedup();
+	for id in mods {
+		doc.add_facet(package_schema.facets, Facet::from_path([FACET_MODS, id]));
+	}
+
+	let mut fin_versions: Vec<String> = package
+		.versions
+		.iter()
+		.filter_map(|v| version_req_lower_bound(v.fin_version.as_ref()?))
+		.map(|v| format!("{}.{}", v.major, v.minor))
+		.collect();
+	fin_versions.sort();
+	fin_versions.dedup();
+	for fin_version in fin_versions {
+		doc.add_facet(package_schema.facets, Facet::from_path([FACET_FIN, &fin_version]));
+	}
+
+	let mut provides: Vec<&String> = package
+		.versions
+		.iter()
+		.flat_map(|v| &v.provides)
+		.collect();
+	provides.sort();
+	provides.dedup();
+	for capability in provides {
+		doc.add_text(package_schema.provides, capability);
+	}
+
+	let mut requires: Vec<&String> = latest_version(&package.versions)
+		.into_iter()
+		.flat_map(|v| &v.eeprom)
+		.flat_map(|e| &e.requirements)
+		.collect();
+	requires.sort();
+	requires.dedup();
+	for requirement in requires {
+		doc.add_text(package_schema.requires, requirement);
 	}
 
+	let mut eeproms = Vec::new();
 	for version in package.versions {
 		let version_data = VersionData {
+			folder: version.folder,
 			fin_version: version.fin_version.map(|v| v.to_string()),
 			game_version: version.game_version.map(|v| v.to_string()),
 			mod_dependencies: version
@@ -91,11 +374,165 @@ pub fn add_package_to_index(
 					version: m.version.map(|v| v.to_string()),
 				})
 				.collect(),
+			package_dependencies: version
+				.package_dependencies
+				.into_iter()
+				.map(|p| PackageDependency {
+					id: p.id,
+					version: p.version.map(|v| v.to_string()),
+				})
+				.collect(),
+			provides: version.provides,
+			conflicts: version.conflicts,
+			channel: version.channel,
+			yanked: version.yanked,
+			yanked_reason: version.yanked_reason,
+			lint: version.lint,
+			eeprom_sizes: version
+				.eeprom
+				.iter()
+				.filter_map(|e| {
+					Some(EepromSize {
+						file: e.name.clone(),
+						size: e.size?,
+						minified: e.minified_size,
+					})
+				})
+				.collect(),
 		};
 
+		let version_data = bitcode::encode(&version_data);
+		for eeprom in version.eeprom {
+			let Some(code) = eeprom.code else {
+				continue;
+			};
+			let mut eeprom_doc = doc!(
+				package_schema.kind => KIND_EEPROM,
+				package_schema.eeprom_package => package_id.clone(),
+				package_schema.eeprom_version => version.version.to_string(),
+				package_schema.eeprom_file => eeprom.name,
+				package_schema.eeprom_title => eeprom.title,
+				package_schema.eeprom_description => eeprom.description,
+				package_schema.eeprom_code => code,
+				package_schema.version_data => version_data.clone(),
+			);
+			for requirement in eeprom.requirements {
+				eeprom_doc.add_text(package_schema.eeprom_requirements, requirement);
+			}
+			if let Some(minified) = eeprom.minified {
+				eeprom_doc.add_text(package_schema.eeprom_minified, minified);
+			}
+			eeproms.push(eeprom_doc);
+		}
+
 		doc.add_text(package_schema.versions, &version.version);
-		doc.add_bytes(package_schema.version_data, bitcode::encode(&version_data));
+		doc.add_bytes(package_schema.version_data, version_data);
 	}
 
-	index_writer.add_document(doc)
+	let opstamp = index_writer.add_document(doc)?;
+	eeproms
+		.into_iter()
+		.try_fold(opstamp, |_, eeprom| index_writer.add_document(eeprom))
+}
+
+/// Reads all versions of the package with the given id from the index,
+/// together with their [VersionData].
+pub fn get_package_versions(
+	searcher: &Searcher,
+	package_schema: &PackageSchema,
+	id: &str,
+) -> Option<Vec<(semver::Version, VersionData)>> {
+	let query = TermQuery::new(
+		Term::from_field_text(package_schema.id, id),
+		IndexRecordOption::Basic,
+	);
+	let (_, address) = searcher
+		.search(&query, &TopDocs::with_limit(1))
+		.ok()?
+		.into_iter()
+		.next()?;
+	let doc: TantivyDocument = searcher.doc(address).ok()?;
+	Some(read_package_versions(&doc, package_schema))
+}
+
+/// Reads the code of an EEPROM file of a package version from the index,
+/// or its minified code, which is [None] if it could not be minified.
+pub fn get_eeprom_code(
+	searcher: &Searcher,
+	package_schema: &PackageSchema,
+	id: &str,
+	version: &semver::Version,
+	file: &str,
+	minified: bool,
+) -> Option<String> {
+	let terms = [
+		(package_schema.eeprom_package, id.to_string()),
+		(package_schema.eeprom_version, version.to_string()),
+		(package_schema.eeprom_file, file.to_string()),
+	];
+	let query = BooleanQuery::new(
+		terms
+			.into_iter()
+			.map(|(field, text)| {
+				let query: Box<dyn Query> = Box::new(TermQuery::new(
+					Term::from_field_text(field, &text),
+					IndexRecordOption::Basic,
+				));
+				(Occur::Must, query)
+			})
+			.collect(),
+	);
+	let (_, address) = searcher
+		.search(&query, &TopDocs::with_limit(1))
+		.ok()?
+		.into_iter()
+		.next()?;
+	let doc: TantivyDocument = searcher.doc(address).ok()?;
+	let field = if minified {
+		package_schema.eeprom_minified
+	} else {
+		package_schema.eeprom_code
+	};
+	Some(doc.get_first(field)?.as_str()?.to_string())
+}
+
+/// Decodes all versions of a package document together with their [VersionData].
+pub fn read_package_versions(
+	doc: &TantivyDocument,
+	package_schema: &PackageSchema,
+) -> Vec<(semver::Version, VersionData)> {
+	doc.get_all(package_schema.versions)
+		.zip(doc.get_all(package_schema.version_data))
+		.filter_map(|(version, data)| {
+			Some((
+				semver::Version::parse(version.as_str()?).ok()?,
+				bitcode::decode::<VersionData>(data.as_bytes()?).ok()?,
+			))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures;
+
+	#[test]
+	fn gets_eeprom_code() {
+		let (searcher, package_schema) = fixtures::index(fixtures::packages());
+
+		let version = semver::Version::new(0, 2, 0);
+		let code = get_eeprom_code(&searcher, &package_schema, "NetBoot", &version, "Client.lua", false);
+		assert_eq!(
+			code.as_deref(),
+			Some("local net = computer.getPCIDevices(classes.NetworkCard)[1]\nevent.listen(net)")
+		);
+		let minified = get_eeprom_code(&searcher, &package_schema, "NetBoot", &version, "Client.lua", true);
+		assert_eq!(
+			minified.as_deref(),
+			Some("local a=computer.getPCIDevices(classes.NetworkCard)[1]event.listen(a)")
+		);
+		let old = semver::Version::new(0, 1, 0);
+		assert_eq!(get_eeprom_code(&searcher, &package_schema, "NetBoot", &old, "Client.lua", false), None);
+	}
 }
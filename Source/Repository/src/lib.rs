@@ -1,6 +1,17 @@
+pub mod compat;
+#[cfg(test)]
+mod fixtures;
+pub mod game;
 pub mod index;
+pub mod lua;
 pub mod metadata;
 pub mod model;
+pub mod reflection;
+pub mod resolve;
+pub mod save;
+pub mod search;
+pub mod smm;
+pub mod tokenizer;
 pub mod util;
 
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,577 @@
+use bitcode::{Decode, Encode};
+use full_moon::ast::{
+	Ast, Block, Call, Expression, FunctionBody, FunctionCall, FunctionName, GenericFor, Index, LocalAssignment,
+	LocalFunction, MethodCall, NumericFor, Parameter, Prefix, Repeat, Suffix, Var, VarExpression,
+};
+use full_moon::node::Node;
+use full_moon::tokenizer::{Position, TokenReference, TokenType};
+use full_moon::visitors::Visitor;
+use full_moon::LuaVersion;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+/// The FIN libraries whose functions count as requirements of an EEPROM.
+const LIBRARIES: [&str; 4] = ["component", "computer", "event", "filesystem"];
+
+/// The requirements added for classes whose name starts with the prefix, so variants like `GPUT1` and `GPUT2`
+/// can be searched together.
+const CLASS_GROUPS: [(&str, &str); 1] = [("GPU", "GPU")];
+
+/// Functions of standard Lua FIN does not provide.
+const UNAVAILABLE_FUNCTIONS: [&str; 3] = ["require", "dofile", "loadfile"];
+/// Libraries of standard Lua FIN does not provide.
+const UNAVAILABLE_LIBRARIES: [&str; 3] = ["io", "debug", "package"];
+/// Members of libraries FIN provides only partially.
+const UNAVAILABLE_MEMBERS: [&str; 1] = ["os.execute"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Encode, Decode)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	/// The EEPROM can not run at all, like for syntax errors.
+	Error,
+	/// The EEPROM fails once it reaches the problem, like when calling functions FIN does not provide.
+	Warning,
+}
+
+/// A problem found in the code of an EEPROM.
+#[derive(Clone, Debug, PartialEq, Serialize, Encode, Decode)]
+pub struct LintMessage {
+	/// The file name of the EEPROM in the version folder.
+	pub file: String,
+	/// The line of the problem, starting at 1.
+	pub line: usize,
+	/// The column of the problem, starting at 1.
+	pub column: usize,
+	pub severity: Severity,
+	pub message: String,
+}
+
+impl LintMessage {
+	fn new(file: &str, position: Position, severity: Severity, message: String) -> Self {
+		LintMessage {
+			file: file.to_string(),
+			line: position.line(),
+			column: position.character(),
+			severity,
+			message,
+		}
+	}
+}
+
+impl Display for LintMessage {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let severity = match self.severity {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+		};
+		write!(f, "{}:{}:{}: {severity}: {}", self.file, self.line, self.column, self.message)
+	}
+}
+
+/// The size in bytes of an EEPROM and of its variant of [minify].
+#[derive(Clone, Debug, PartialEq, Serialize, Encode, Decode)]
+pub struct EepromSize {
+	/// The file name of the EEPROM in the version folder.
+	pub file: String,
+	pub size: u64,
+	/// [None] if the EEPROM could not be minified because of syntax errors.
+	pub minified: Option<u64>,
+}
+
+/// Parses Lua 5.4 code, as used by FIN.
+pub fn parse(code: &str) -> Result<Ast, Vec<full_moon::Error>> {
+	full_moon::parse_fallible(code, LuaVersion::lua54()).into_result()
+}
+
+/// Converts the errors of [parse] of the EEPROM file to lint messages.
+pub fn syntax_errors(file: &str, errors: &[full_moon::Error]) -> Vec<LintMessage> {
+	errors
+		.iter()
+		.map(|e| LintMessage::new(file, e.range().0, Severity::Error, e.error_message().to_string()))
+		.collect()
+}
+
+/// Finds the uses of standard Lua functions and libraries FIN does not provide in the EEPROM file,
+/// like `require`, `io.open` or `os.execute`.
+pub fn incompatibilities(file: &str, ast: &Ast) -> Vec<LintMessage> {
+	let mut visitor = IncompatibilityVisitor {
+		file,
+		messages: Vec::new(),
+	};
+	visitor.visit_ast(ast);
+	visitor.messages
+}
+
+/// Returns the FIN APIs and component classes the code uses, sorted and without duplicates.
+/// Library members are listed with their library like `component.findComponent` or `event.listen`,
+/// classes referenced by `classes.X` by their name like `GPUT1` and `InternetCard`.
+pub fn requirements(ast: &Ast) -> Vec<String> {
+	let mut visitor = RequirementVisitor::default();
+	visitor.visit_ast(ast);
+	visitor.requirements.into_iter().collect()
+}
+
+/// The FIN reflection names the code uses, see [references].
+#[derive(Debug, Default, PartialEq)]
+pub struct References {
+	/// The classes and structs referenced like `classes.GPUT1` or `structs.Vector`.
+	pub types: BTreeSet<String>,
+	/// The names of the called methods like `bindScreen` of `gpu:bindScreen()`.
+	pub methods: BTreeSet<String>,
+	/// The names of the accessed members like `isActive` of `screen.isActive`, except of called functions.
+	pub properties: BTreeSet<String>,
+}
+
+/// Returns the classes, structs, methods and properties the code uses, to check them against the FIN reflection documentation.
+pub fn references(ast: &Ast) -> References {
+	let mut visitor = ReferenceVisitor::default();
+	visitor.visit_ast(ast);
+	visitor.references
+}
+
+#[derive(Default)]
+struct ReferenceVisitor {
+	references: References,
+}
+
+impl ReferenceVisitor {
+	/// Adds the type or the properties of an access path, without its last member if that one gets called.
+	fn add_path(&mut self, path: &[String], called: bool) {
+		match path {
+			[first, name, ..] if first == "classes" || first == "structs" => {
+				self.references.types.insert(name.clone());
+			}
+			[_, members @ ..] => {
+				let members = match members.split_last() {
+					Some((_, members)) if called => members,
+					_ => members,
+				};
+				self.references.properties.extend(members.iter().cloned());
+			}
+			[] => {}
+		}
+	}
+}
+
+impl Visitor for ReferenceVisitor {
+	fn visit_function_call(&mut self, call: &FunctionCall) {
+		let path = path(call.prefix(), call.suffixes());
+		// the path ends at the first suffix that is no index, like the call of its last member
+		let called = matches!(
+			call.suffixes().nth(path.len().saturating_sub(1)),
+			Some(Suffix::Call(Call::AnonymousCall(_)))
+		);
+		self.add_path(&path, called);
+	}
+
+	fn visit_var_expression(&mut self, var: &VarExpression) {
+		self.add_path(&path(var.prefix(), var.suffixes()), false);
+	}
+
+	fn visit_method_call(&mut self, call: &MethodCall) {
+		self.references.methods.insert(name(call.name()));
+	}
+}
+
+#[derive(Default)]
+struct RequirementVisitor {
+	requirements: BTreeSet<String>,
+}
+
+fn name(token: &TokenReference) -> String {
+	token.token().to_string()
+}
+
+/// Returns the names of an access path like `component.findComponent` or `classes["Screen"]`,
+/// up to the first suffix that is no constant index.
+fn path<'a>(prefix: &Prefix, suffixes: impl Iterator<Item = &'a Suffix>) -> Vec<String> {
+	let Prefix::Name(first) = prefix else {
+		return Vec::new();
+	};
+	let mut path = vec![name(first)];
+	for suffix in suffixes {
+		match suffix {
+			Suffix::Index(Index::Dot { name: member, .. }) => path.push(name(member)),
+			Suffix::Index(Index::Brackets { expression, .. }) => match expression.as_ref() {
+				Expression::String(s) => path.push(
+					name(s)
+						.trim_matches(|c| c == '"' || c == '\'')
+						.to_string(),
+				),
+				_ => break,
+			},
+			_ => break,
+		}
+	}
+	path
+}
+
+impl RequirementVisitor {
+	fn add_path(&mut self, path: &[String]) {
+		let [first, member, ..] = path else {
+			return;
+		};
+		if first == "classes" {
+			for (prefix, group) in CLASS_GROUPS {
+				if member.starts_with(prefix) {
+					self.requirements.insert(group.to_string());
+				}
+			}
+			self.requirements.insert(member.clone());
+		} else if LIBRARIES.contains(&first.as_str()) {
+			self.requirements.insert(format!("{first}.{member}"));
+		}
+	}
+}
+
+impl Visitor for RequirementVisitor {
+	fn visit_function_call(&mut self, call: &FunctionCall) {
+		self.add_path(&path(call.prefix(), call.suffixes()));
+	}
+
+	fn visit_var_expression(&mut self, var: &VarExpression) {
+		self.add_path(&path(var.prefix(), var.suffixes()));
+	}
+}
+
+struct IncompatibilityVisitor<'a> {
+	file: &'a str,
+	messages: Vec<LintMessage>,
+}
+
+impl IncompatibilityVisitor<'_> {
+	fn check(&mut self, prefix: &Prefix, path: &[String], called: bool) {
+		let Prefix::Name(first) = prefix else {
+			return;
+		};
+		let message = match path {
+			[function] if called && UNAVAILABLE_FUNCTIONS.contains(&function.as_str()) => {
+				format!("`{function}` is not available in FIN")
+			}
+			[library, member, ..] if UNAVAILABLE_LIBRARIES.contains(&library.as_str()) => {
+				format!("`{library}.{member}` is not available in FIN, it has no {library} library")
+			}
+			[library, member, ..] if UNAVAILABLE_MEMBERS.contains(&format!("{library}.{member}").as_str()) => {
+				format!("`{library}.{member}` is not available in FIN")
+			}
+			_ => return,
+		};
+		self.messages.push(LintMessage::new(
+			self.file,
+			first.token().start_position(),
+			Severity::Warning,
+			message,
+		));
+	}
+}
+
+impl Visitor for IncompatibilityVisitor<'_> {
+	fn visit_function_call(&mut self, call: &FunctionCall) {
+		let path = path(call.prefix(), call.suffixes());
+		let called = matches!(call.suffixes().nth(path.len().saturating_sub(1)), Some(Suffix::Call(_)));
+		self.check(call.prefix(), &path, called);
+	}
+
+	fn visit_var_expression(&mut self, var: &VarExpression) {
+		self.check(var.prefix(), &path(var.prefix(), var.suffixes()), false);
+	}
+}
+
+const KEYWORDS: [&str; 22] = [
+	"and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in", "local",
+	"nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Minifies the code to take less space on an EEPROM, comments and whitespace are left out
+/// and local variables get the shortest names not used by any global the code uses.
+pub fn minify(ast: &Ast) -> String {
+	let renames = rename_locals(ast);
+	let mut minified = String::new();
+	let mut after_number = false;
+	// the tokens of brackets come in pairs, not in the order of the code
+	let mut tokens: Vec<_> = ast.nodes().tokens().map(|t| t.token()).collect();
+	tokens.sort_by_key(|t| t.start_position().bytes());
+	for token in tokens {
+		let text = match token.token_type() {
+			TokenType::Identifier { .. } => renames
+				.get(&token.start_position().bytes())
+				.cloned()
+				.unwrap_or_else(|| token.to_string()),
+			_ => token.to_string(),
+		};
+		if needs_space(&minified, &text, after_number) {
+			minified.push(' ');
+		}
+		minified.push_str(&text);
+		after_number = matches!(token.token_type(), TokenType::Number { .. });
+	}
+	minified
+}
+
+/// Whether adjacent tokens need a space between them, so they are not read as one token or a comment.
+fn needs_space(before: &str, after: &str, after_number: bool) -> bool {
+	const JOINING: &str = "-.=<>~/:[";
+	let (Some(last), Some(first)) = (before.chars().last(), after.chars().next()) else {
+		return false;
+	};
+	let word = |c: char| c.is_alphanumeric() || c == '_';
+	(word(last) && word(first))
+		|| (JOINING.contains(last) && JOINING.contains(first))
+		|| (after_number && first == '.')
+}
+
+/// Returns the i-th short name, like `a`, `Z` or `a1`.
+/// Names starting with `_` are left out, so no local becomes `_ENV`.
+fn short_name(mut i: usize) -> String {
+	const FIRST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+	const REST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_0123456789";
+	let mut name = vec![FIRST[i % FIRST.len()]];
+	i /= FIRST.len();
+	while i > 0 {
+		i -= 1;
+		name.push(REST[i % REST.len()]);
+		i /= REST.len();
+	}
+	String::from_utf8(name).unwrap()
+}
+
+/// Returns the new names of the identifiers of local variables by their byte offset.
+/// Every local gets its own name, so no local shadows another one, the most used locals get the shortest names.
+fn rename_locals(ast: &Ast) -> HashMap<usize, String> {
+	let mut resolver = LocalResolver::default();
+	resolver.visit_ast(ast);
+	let mut locals = resolver.locals;
+	locals.sort_by_key(|offsets| std::cmp::Reverse(offsets.len()));
+	let mut names = (0..)
+		.map(short_name)
+		.filter(|name| !KEYWORDS.contains(&name.as_str()) && !resolver.globals.contains(name));
+	locals
+		.into_iter()
+		.flat_map(|offsets| {
+			let name = names.next().unwrap();
+			offsets.into_iter().map(move |offset| (offset, name.clone()))
+		})
+		.collect()
+}
+
+/// Resolves the uses of names to the local variables in scope, following the scoping rules of Lua.
+#[derive(Default)]
+struct LocalResolver {
+	/// The locals of the enclosing blocks, innermost last, as name and index in `locals`.
+	scopes: Vec<Vec<(String, usize)>>,
+	/// Locals to declare once their block is entered, like parameters and loop variables.
+	pending: Vec<(*const Block, Vec<(String, usize)>)>,
+	/// Locals to declare at the end of their `local` statement, as they are not in scope of its expressions.
+	assigning: Vec<Vec<(String, usize)>>,
+	/// The blocks of repeat loops, their scope ends after the until condition.
+	repeat_blocks: Vec<*const Block>,
+	/// The byte offsets of the declaration and all uses of each local.
+	locals: Vec<Vec<usize>>,
+	/// The names used without a local in scope.
+	globals: HashSet<String>,
+}
+
+fn offset(token: &TokenReference) -> usize {
+	token.token().start_position().bytes()
+}
+
+impl LocalResolver {
+	fn declare(&mut self, token: &TokenReference) -> (String, usize) {
+		self.locals.push(vec![offset(token)]);
+		(name(token), self.locals.len() - 1)
+	}
+
+	fn resolve(&mut self, token: &TokenReference) {
+		let name = name(token);
+		let local = self
+			.scopes
+			.iter()
+			.rev()
+			.flat_map(|scope| scope.iter().rev())
+			.find(|(local, _)| *local == name);
+		match local {
+			Some((_, i)) => self.locals[*i].push(offset(token)),
+			None => {
+				self.globals.insert(name);
+			}
+		}
+	}
+
+	fn declare_in_block(&mut self, block: &Block, tokens: Vec<&TokenReference>) {
+		let locals = tokens.into_iter().map(|t| self.declare(t)).collect();
+		self.pending.push((block, locals));
+	}
+}
+
+impl Visitor for LocalResolver {
+	fn visit_block(&mut self, block: &Block) {
+		let locals = match self.pending.iter().position(|(b, _)| std::ptr::eq(*b, block)) {
+			Some(i) => self.pending.remove(i).1,
+			None => Vec::new(),
+		};
+		self.scopes.push(locals);
+	}
+
+	fn visit_block_end(&mut self, block: &Block) {
+		if !self.repeat_blocks.last().is_some_and(|b| std::ptr::eq(*b, block)) {
+			self.scopes.pop();
+		}
+	}
+
+	fn visit_repeat(&mut self, repeat: &Repeat) {
+		self.repeat_blocks.push(repeat.block());
+	}
+
+	fn visit_repeat_end(&mut self, _: &Repeat) {
+		self.repeat_blocks.pop();
+		self.scopes.pop();
+	}
+
+	fn visit_local_assignment(&mut self, local: &LocalAssignment) {
+		let locals = local.names().iter().map(|t| self.declare(t)).collect();
+		self.assigning.push(locals);
+	}
+
+	fn visit_local_assignment_end(&mut self, _: &LocalAssignment) {
+		let locals = self.assigning.pop().unwrap_or_default();
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.extend(locals);
+		}
+	}
+
+	fn visit_local_function(&mut self, function: &LocalFunction) {
+		let local = self.declare(function.name());
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.push(local);
+		}
+	}
+
+	fn visit_function_body(&mut self, body: &FunctionBody) {
+		let parameters = body
+			.parameters()
+			.iter()
+			.filter_map(|p| match p {
+				Parameter::Name(name) => Some(name),
+				_ => None,
+			})
+			.collect();
+		self.declare_in_block(body.block(), parameters);
+	}
+
+	fn visit_numeric_for(&mut self, numeric_for: &NumericFor) {
+		self.declare_in_block(numeric_for.block(), vec![numeric_for.index_variable()]);
+	}
+
+	fn visit_generic_for(&mut self, generic_for: &GenericFor) {
+		self.declare_in_block(generic_for.block(), generic_for.names().iter().collect());
+	}
+
+	fn visit_var(&mut self, var: &Var) {
+		if let Var::Name(name) = var {
+			self.resolve(name);
+		}
+	}
+
+	fn visit_prefix(&mut self, prefix: &Prefix) {
+		if let Prefix::Name(name) = prefix {
+			self.resolve(name);
+		}
+	}
+
+	fn visit_function_name(&mut self, function_name: &FunctionName) {
+		if let Some(name) = function_name.names().iter().next() {
+			self.resolve(name);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_requirements() {
+		let ast = parse(
+			"local gpu = computer.getPCIDevices(classes.GPUT2)[1]\n\
+			local screen = component.proxy(component.findComponent(classes[\"Screen\"])[1])\n\
+			event.listen(gpu)\n\
+			filesystem.initFileSystem(\"/dev\")\n\
+			gpu:bindScreen(screen)",
+		)
+		.unwrap();
+		assert_eq!(
+			requirements(&ast),
+			[
+				"GPU",
+				"GPUT2",
+				"Screen",
+				"component.findComponent",
+				"component.proxy",
+				"computer.getPCIDevices",
+				"event.listen",
+				"filesystem.initFileSystem"
+			]
+		);
+	}
+
+	#[test]
+	fn reports_syntax_errors() {
+		let errors = syntax_errors("EEPROM.lua", &parse("local x = 1\nif x then\n  print(x\nend").unwrap_err());
+		assert!(!errors.is_empty());
+		assert_eq!(errors[0].file, "EEPROM.lua");
+		assert_eq!(errors[0].severity, Severity::Error);
+		assert_eq!(errors[0].to_string(), "EEPROM.lua:3:8: error: expected `)` to close function call");
+	}
+
+	#[test]
+	fn reports_incompatibilities() {
+		let ast = parse(
+			"local json = require(\"json\")\n\
+			local f = io.open(\"x\")\n\
+			os.execute(\"ls\")\n\
+			print(os.time(), debug.traceback())",
+		)
+		.unwrap();
+		let messages: Vec<_> = incompatibilities("EEPROM.lua", &ast)
+			.into_iter()
+			.map(|m| (m.line, m.column, m.message))
+			.collect();
+		assert_eq!(
+			messages,
+			[
+				(1, 14, "`require` is not available in FIN".to_string()),
+				(2, 11, "`io.open` is not available in FIN, it has no io library".to_string()),
+				(3, 1, "`os.execute` is not available in FIN".to_string()),
+				(4, 18, "`debug.traceback` is not available in FIN, it has no debug library".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn minifies() {
+		let code = "-- counts down\n\
+			local count = 10 -- start\n\
+			local function tick(count)\n\
+			\tlocal x <const> = count - -1\n\
+			\treturn x .. 1\n\
+			end\n\
+			for i = count, 1, -1 do print(tick(i), i) end\n\
+			repeat local done = true until done\n\
+			a = count";
+		assert_eq!(
+			minify(&parse(code).unwrap()),
+			"local b=10 local function d(e)local f<const> =e- -1 return f..1 end for c=b,1,-1 do print(d(c),c)end repeat local g=true until g a=b"
+		);
+	}
+
+	#[test]
+	fn minified_locals_keep_their_scope() {
+		// `a` is global and must not be shadowed, the `x` of the expression is the outer one
+		let code = "local x = 1\ndo local x = x + 1 print(x) end\nprint(x, a)";
+		assert_eq!(
+			minify(&parse(code).unwrap()),
+			"local b=1 do local c=b+1 print(c)end print(b,a)"
+		);
+	}
+}
@@ -1,31 +1,50 @@
-use crate::util::{deserialize_semver_req_opt, serialize_semver_req_opt};
+use crate::compat::Channel;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Package {
 	pub name: String,
+	/// The current version of the package, it should have a version folder.
+	/// Parsed leniently by [crate::model::Package::from_metadata].
+	#[serde(default)]
+	pub version: Option<String>,
 	pub short_description: String,
 	pub tags: Vec<String>,
 	pub authors: Vec<String>,
+	#[serde(default)]
+	pub deprecated: bool,
+	/// The id of the package that should be used instead of this deprecated package.
+	#[serde(default)]
+	pub replaced_by: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Version {
-	#[serde(
-		serialize_with = "serialize_semver_req_opt",
-		deserialize_with = "deserialize_semver_req_opt",
-		default
-	)]
-	pub fin_version: Option<semver::VersionReq>,
-	#[serde(
-		serialize_with = "serialize_semver_req_opt",
-		deserialize_with = "deserialize_semver_req_opt",
-		default
-	)]
-	pub game_version: Option<semver::VersionReq>,
+	/// Parsed leniently by [crate::model::Version::from_metadata], like all version requirements of the metadata.
+	#[serde(default)]
+	pub fin_version: Option<String>,
+	/// Accepts release names and build numbers besides semver, see [crate::game::parse_game_version_req].
+	#[serde(default)]
+	pub game_version: Option<String>,
 	#[serde(default)]
 	pub mod_dependencies: Vec<ModDependency>,
+	#[serde(default)]
+	pub package_dependencies: Vec<PackageDependency>,
+	#[serde(default)]
+	pub provides: Vec<String>,
+	#[serde(default)]
+	pub conflicts: Vec<String>,
+	/// Overrides the release channel, by default pre-releases are in the beta channel.
+	#[serde(default)]
+	pub channel: Option<Channel>,
+	#[serde(default)]
+	pub yanked: bool,
+	#[serde(default)]
+	pub yanked_reason: Option<String>,
+	/// When this version got released, by default the date of the first commit of the version folder.
+	#[serde(default)]
+	pub released: Option<toml::value::Datetime>,
 	#[serde(alias = "EEPROM", default)]
 	pub eeprom: Vec<EEPROM>,
 }
@@ -33,12 +52,15 @@ pub struct Version {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ModDependency {
 	pub id: String,
-	#[serde(
-		serialize_with = "serialize_semver_req_opt",
-		deserialize_with = "deserialize_semver_req_opt",
-		default
-	)]
-	pub version: Option<semver::VersionReq>,
+	#[serde(default)]
+	pub version: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PackageDependency {
+	pub id: String,
+	#[serde(default)]
+	pub version: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
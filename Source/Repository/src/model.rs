@@ -1,6 +1,9 @@
 use std::fmt::{Display, Formatter, Write};
 use serde::Serialize;
-use crate::util::{serialize_semver_req, serialize_semver_req_opt, serialize_semver};
+use crate::compat::Channel;
+use crate::lua::{LintMessage, Severity};
+use crate::game::parse_game_version_req;
+use crate::util::{parse_version_lenient, parse_version_req_lenient, serialize_semver_req, serialize_semver_req_opt, serialize_semver, serialize_semver_opt, toml_datetime_to_timestamp};
 #[derive(Clone, Serialize)]
 pub struct Package {
 	pub id: String,
@@ -9,7 +12,16 @@ pub struct Package {
 	pub readme: Readme,
 	pub tags: Vec<String>,
 	pub authors: Vec<String>,
+	/// The current version of the package named by its metadata.
+	#[serde(serialize_with="serialize_semver_opt")]
+	pub version: Option<semver::Version>,
 	pub versions: Vec<Version>,
+	pub deprecated: bool,
+	pub replaced_by: Option<String>,
+	/// Unix timestamp of the first release of the package.
+	pub published: Option<i64>,
+	/// Unix timestamp of the last change to the package.
+	pub updated: Option<i64>,
 }
 
 #[derive(Clone, Serialize)]
@@ -31,12 +43,26 @@ impl Display for Readme {
 pub struct Version {
 	#[serde(serialize_with="serialize_semver")]
 	pub version: semver::Version,
+	/// The name of the folder containing this version inside the package folder.
+	pub folder: String,
 	#[serde(serialize_with="serialize_semver_req_opt")]
 	pub fin_version: Option<semver::VersionReq>,
 	#[serde(serialize_with="serialize_semver_req_opt")]
 	pub game_version: Option<semver::VersionReq>,
 	pub mod_dependencies: Vec<ModDependency>,
+	pub package_dependencies: Vec<PackageDependency>,
+	pub provides: Vec<String>,
+	pub conflicts: Vec<String>,
+	pub channel: Channel,
+	pub yanked: bool,
+	pub yanked_reason: Option<String>,
+	/// Unix timestamp of the release of this version.
+	pub published: Option<i64>,
+	/// Unix timestamp of the last change to this version.
+	pub updated: Option<i64>,
 	pub eeprom: Vec<EEPROM>,
+	/// The problems found in the EEPROMs by the indexer, see [crate::lua].
+	pub lint: Vec<LintMessage>,
 }
 
 #[derive(Clone, Serialize)]
@@ -46,21 +72,65 @@ pub struct ModDependency {
 	pub version: Option<semver::VersionReq>,
 }
 
+#[derive(Clone, Serialize)]
+pub struct PackageDependency {
+	pub id: String,
+	#[serde(serialize_with="serialize_semver_req_opt")]
+	pub version: Option<semver::VersionReq>,
+}
+
 #[derive(Clone, Serialize)]
 pub struct EEPROM {
 	pub name: String,
 	pub title: String,
 	pub description: String,
+	/// The Lua source of the EEPROM, only read by the indexer.
+	#[serde(skip)]
+	pub code: Option<String>,
+	/// The FIN APIs and classes the code uses, see [crate::lua::requirements], only set by the indexer.
+	#[serde(skip)]
+	pub requirements: Vec<String>,
+	/// The code of [crate::lua::minify], only set by the indexer.
+	#[serde(skip)]
+	pub minified: Option<String>,
+	/// The size of the code in bytes.
+	pub size: Option<u64>,
+	/// The size of the minified code in bytes.
+	pub minified_size: Option<u64>,
+}
+
+/// Parses a version of the metadata leniently and keeps the warning if it is not strictly valid.
+fn parse_version(name: &str, version: Option<String>, warnings: &mut Vec<String>) -> Result<Option<semver::Version>, String> {
+	let Some(version) = version else {
+		return Ok(None);
+	};
+	let version = parse_version_lenient(&version).map_err(|e| format!("invalid {name} '{version}': {e}"))?;
+	warnings.extend(version.warning);
+	Ok(Some(version.value))
+}
+
+/// Parses a version requirement of the metadata leniently and keeps the warning if it is not strictly valid.
+fn parse_version_req(name: &str, requirement: Option<String>, warnings: &mut Vec<String>) -> Result<Option<semver::VersionReq>, String> {
+	let Some(requirement) = requirement else {
+		return Ok(None);
+	};
+	let requirement = parse_version_req_lenient(&requirement).map_err(|e| format!("invalid {name} '{requirement}': {e}"))?;
+	warnings.extend(requirement.warning);
+	Ok(Some(requirement.value))
 }
 
 impl Package {
+	/// Returns the package and the warnings of versions in the metadata that are not strictly valid,
+	/// or an error if a version can't be parsed at all.
 	pub fn from_metadata(
 		id: String,
 		readme: Readme,
 		versions: Vec<Version>,
 		metadata: crate::metadata::Package,
-	) -> Self {
-		Package {
+	) -> Result<(Self, Vec<String>), String> {
+		let mut warnings = Vec::new();
+		let package = Package {
+			version: parse_version("version", metadata.version, &mut warnings)?,
 			id,
 			name: metadata.name,
 			short_description: metadata.short_description,
@@ -68,6 +138,115 @@ impl Package {
 			tags: metadata.tags,
 			authors: metadata.authors,
 			versions,
-		}
+			deprecated: metadata.deprecated,
+			replaced_by: metadata.replaced_by,
+			published: None,
+			updated: None,
+		};
+		Ok((package, warnings))
+	}
+}
+
+impl Version {
+	/// Returns the version and the warnings of versions in the metadata that are not strictly valid,
+	/// or an error if a version can't be parsed at all.
+	pub fn from_metadata(
+		version: semver::Version,
+		folder: String,
+		metadata: crate::metadata::Version,
+	) -> Result<(Self, Vec<String>), String> {
+		let mut warnings = Vec::new();
+		let game_version = metadata
+			.game_version
+			.as_deref()
+			.map(parse_game_version_req)
+			.transpose()?;
+		let version = Version {
+			folder,
+			channel: metadata.channel.unwrap_or(Channel::of_version(&version)),
+			version,
+			fin_version: parse_version_req("fin_version", metadata.fin_version, &mut warnings)?,
+			game_version,
+			mod_dependencies: metadata
+				.mod_dependencies
+				.into_iter()
+				.map(|d| {
+					Ok(ModDependency {
+						version: parse_version_req(&format!("version of mod '{}'", d.id), d.version, &mut warnings)?,
+						id: d.id,
+					})
+				})
+				.collect::<Result<_, String>>()?,
+			package_dependencies: metadata
+				.package_dependencies
+				.into_iter()
+				.map(|d| {
+					Ok(PackageDependency {
+						version: parse_version_req(&format!("version of package '{}'", d.id), d.version, &mut warnings)?,
+						id: d.id,
+					})
+				})
+				.collect::<Result<_, String>>()?,
+			provides: metadata.provides,
+			conflicts: metadata.conflicts,
+			yanked: metadata.yanked,
+			yanked_reason: metadata.yanked_reason,
+			published: metadata.released.as_ref().and_then(toml_datetime_to_timestamp),
+			updated: None,
+			eeprom: metadata
+				.eeprom
+				.into_iter()
+				.map(|e| EEPROM {
+					name: e.name,
+					title: e.title,
+					description: e.description,
+					code: None,
+					requirements: Vec::new(),
+					minified: None,
+					size: None,
+					minified_size: None,
+				})
+				.collect(),
+			lint: Vec::new(),
+		};
+		Ok((version, warnings))
+	}
+
+	/// The number of lint messages that are errors.
+	pub fn lint_errors(&self) -> usize {
+		self.lint
+			.iter()
+			.filter(|m| m.severity == Severity::Error)
+			.count()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn returns_warnings_of_metadata() {
+		let metadata = toml::from_str(
+			"name = \"Net-Boot\"\nversion = \"1.0\"\nshort_description = \"\"\ntags = []\nauthors = []",
+		)
+		.unwrap();
+		let (package, warnings) =
+			Package::from_metadata("NetBoot".to_string(), Readme::Markdown(String::new()), Vec::new(), metadata).unwrap();
+		assert_eq!(package.version, Some(semver::Version::new(1, 0, 0)));
+		assert_eq!(warnings, ["'1.0' is not a valid semver version, it is read as '1.0.0'"]);
+
+		let metadata = toml::from_str(
+			"fin_version = \"0.3.19+\"\n[[mod_dependencies]]\nid = \"SML\"\nversion = \">=3.8 <4\"",
+		)
+		.unwrap();
+		let (version, warnings) =
+			Version::from_metadata(semver::Version::new(1, 0, 0), "v1.0.0".to_string(), metadata).unwrap();
+		assert_eq!(version.fin_version.unwrap().to_string(), ">=0.3.19");
+		assert_eq!(version.mod_dependencies[0].version.as_ref().unwrap().to_string(), ">=3.8, <4");
+		assert_eq!(warnings.len(), 2);
+
+		let metadata = toml::from_str("fin_version = \"newest\"").unwrap();
+		assert!(Version::from_metadata(semver::Version::new(1, 0, 0), "v1.0.0".to_string(), metadata).is_err());
 	}
 }
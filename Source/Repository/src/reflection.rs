@@ -0,0 +1,214 @@
+use crate::lua::References;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// A class, struct, function or property of the reflection documentation.
+/// FIN names them by `internalName`, some exports only by `name`, both are accepted.
+#[derive(Deserialize)]
+struct Entry {
+	name: Option<String>,
+	#[serde(rename = "internalName")]
+	internal_name: Option<String>,
+	#[serde(default)]
+	functions: Vec<Entry>,
+	#[serde(default)]
+	properties: Vec<Entry>,
+}
+
+impl Entry {
+	fn names(&self) -> impl Iterator<Item = &String> {
+		self.name.iter().chain(&self.internal_name)
+	}
+}
+
+/// The reflection documentation FIN exports as JSON, only the names of classes, structs, functions and properties are read.
+#[derive(Deserialize)]
+struct Documentation {
+	#[serde(default, alias = "Classes")]
+	classes: Vec<Entry>,
+	#[serde(default, alias = "Structs")]
+	structs: Vec<Entry>,
+}
+
+/// The classes, structs, methods and properties defined by a FIN release.
+pub struct Release {
+	pub version: semver::Version,
+	types: HashSet<String>,
+	methods: HashSet<String>,
+	properties: HashSet<String>,
+}
+
+impl Release {
+	/// Reads the reflection documentation FIN exported for the release.
+	pub fn from_json(version: semver::Version, json: &str) -> serde_json::Result<Self> {
+		let documentation: Documentation = serde_json::from_str(json)?;
+		let entries = || documentation.classes.iter().chain(&documentation.structs);
+		Ok(Release {
+			version,
+			types: entries().flat_map(Entry::names).cloned().collect(),
+			methods: entries()
+				.flat_map(|e| &e.functions)
+				.flat_map(Entry::names)
+				.cloned()
+				.collect(),
+			properties: entries()
+				.flat_map(|e| &e.properties)
+				.flat_map(Entry::names)
+				.cloned()
+				.collect(),
+		})
+	}
+
+	/// Returns the references that are not defined by this release, but by any of the releases.
+	fn missing(&self, references: &References, releases: &[Release]) -> Vec<String> {
+		let types = references
+			.types
+			.iter()
+			.filter(|t| !self.types.contains(*t) && releases.iter().any(|r| r.types.contains(*t)))
+			.cloned();
+		let methods = references
+			.methods
+			.iter()
+			.filter(|m| !self.methods.contains(*m) && releases.iter().any(|r| r.methods.contains(*m)))
+			.map(|m| format!(":{m}()"));
+		let properties = references
+			.properties
+			.iter()
+			.filter(|p| !self.properties.contains(*p) && releases.iter().any(|r| r.properties.contains(*p)))
+			.map(|p| format!(".{p}"));
+		types.chain(methods).chain(properties).collect()
+	}
+}
+
+/// Which releases support the code of a version.
+/// Methods and properties no release defines are ignored, as they most likely belong to Lua strings or tables.
+pub struct Support {
+	/// The releases defining all classes, structs, methods and properties the code uses.
+	pub supported: Vec<semver::Version>,
+	/// The releases of the declared FIN version requirement that do not support the code, with what they are missing.
+	pub missing: Vec<(semver::Version, Vec<String>)>,
+	/// The classes and structs no release defines, most likely misspelled.
+	pub unknown_types: Vec<String>,
+	/// The tightest requirement of the supported releases, if the declared requirement includes unsupported ones.
+	pub suggestion: Option<semver::VersionReq>,
+}
+
+/// Checks the references of the code of a version against the releases, sorted by version,
+/// and the declared FIN version requirement of the version.
+pub fn check(releases: &[Release], references: &References, declared: Option<&semver::VersionReq>) -> Support {
+	let declared_matches = |version: &semver::Version| declared.is_none_or(|req| req.matches(version));
+	let mut supported = Vec::new();
+	let mut missing = Vec::new();
+	for release in releases {
+		let names = release.missing(references, releases);
+		if names.is_empty() {
+			supported.push(release.version.clone());
+		} else if declared_matches(&release.version) {
+			missing.push((release.version.clone(), names));
+		}
+	}
+	let unknown_types = references
+		.types
+		.iter()
+		.filter(|t| !releases.iter().any(|r| r.types.contains(*t)))
+		.cloned()
+		.collect();
+
+	// the supported releases from the first supported one of the requirement up to the next unsupported one
+	let suggestion = supported
+		.iter()
+		.find(|v| declared_matches(v))
+		.filter(|_| !missing.is_empty())
+		.and_then(|lowest| {
+			let next_unsupported = releases
+				.iter()
+				.map(|r| &r.version)
+				.find(|v| *v > lowest && !supported.contains(v));
+			let requirement = match next_unsupported {
+				Some(upper) => format!(">={lowest}, <{upper}"),
+				None => format!(">={lowest}"),
+			};
+			semver::VersionReq::parse(&requirement).ok()
+		});
+
+	Support {
+		supported,
+		missing,
+		unknown_types,
+		suggestion,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn release(version: &str, classes: &[&str], functions: &[&str]) -> Release {
+		release_with_properties(version, classes, functions, &[])
+	}
+
+	fn release_with_properties(version: &str, classes: &[&str], functions: &[&str], properties: &[&str]) -> Release {
+		let entries = |names: &[&str]| -> Vec<_> { names.iter().map(|n| serde_json::json!({ "internalName": n })).collect() };
+		let (functions, properties) = (entries(functions), entries(properties));
+		let classes: Vec<_> = classes
+			.iter()
+			.map(|c| serde_json::json!({ "internalName": c, "functions": functions, "properties": properties }))
+			.collect();
+		let json = serde_json::json!({ "classes": classes }).to_string();
+		Release::from_json(semver::Version::parse(version).unwrap(), &json).unwrap()
+	}
+
+	#[test]
+	fn suggests_tighter_fin_version() {
+		let releases = [
+			release("0.3.0", &["GPUT1"], &["bindScreen"]),
+			release("0.3.19", &["GPUT1", "GPUT2"], &["bindScreen", "flush"]),
+			release("0.4.0", &["GPUT1", "GPUT2"], &["bindScreen", "flush"]),
+		];
+		let ast = crate::lua::parse(
+			"local gpu = computer.getPCIDevices(classes.GPUT2)[1]\n\
+			gpu:bindScreen(classes.Screen)\n\
+			gpu:flush()\n\
+			print((\"x\"):sub(1))",
+		)
+		.unwrap();
+		let references = crate::lua::references(&ast);
+
+		let declared = semver::VersionReq::parse(">=0.3.0").unwrap();
+		let support = check(&releases, &references, Some(&declared));
+		assert_eq!(support.supported, [semver::Version::new(0, 3, 19), semver::Version::new(0, 4, 0)]);
+		assert_eq!(
+			support.missing,
+			[(semver::Version::new(0, 3, 0), vec!["GPUT2".to_string(), ":flush()".to_string()])]
+		);
+		assert_eq!(support.unknown_types, ["Screen"]);
+		assert_eq!(support.suggestion.unwrap().to_string(), ">=0.3.19");
+
+		let declared = semver::VersionReq::parse(">=0.3.19").unwrap();
+		let support = check(&releases, &references, Some(&declared));
+		assert!(support.missing.is_empty());
+		assert_eq!(support.suggestion, None);
+	}
+
+	#[test]
+	fn checks_properties() {
+		let releases = [
+			release_with_properties("0.3.0", &["Screen"], &["getSize"], &[]),
+			release_with_properties("0.3.19", &["Screen"], &["getSize"], &["isActive"]),
+		];
+		let ast = crate::lua::parse(
+			"local screen = component.proxy(component.findComponent(classes.Screen)[1])\n\
+			local w, h = screen:getSize()\n\
+			if screen.isActive then print(screen.nick, config.width) end",
+		)
+		.unwrap();
+		let references = crate::lua::references(&ast);
+		assert!(references.properties.contains("isActive"));
+		assert!(!references.properties.contains("proxy"));
+
+		let support = check(&releases, &references, None);
+		assert_eq!(support.supported, [semver::Version::new(0, 3, 19)]);
+		assert_eq!(support.missing, [(semver::Version::new(0, 3, 0), vec![".isActive".to_string()])]);
+		assert_eq!(support.suggestion.unwrap().to_string(), ">=0.3.19");
+	}
+}
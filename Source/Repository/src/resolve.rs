@@ -0,0 +1,547 @@
+use crate::compat::{incompatibility, matches_channel, Incompatibility, QueryVersions};
+use crate::index::{get_package_versions, PackageSchema, VersionData};
+use crate::util::parse_version_req_lenient;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use tantivy::Searcher;
+
+/// Provides all known versions of a package to the [Resolver].
+pub trait PackageSource {
+	fn package_versions(&self, id: &str) -> Option<Vec<(semver::Version, VersionData)>>;
+}
+
+impl PackageSource for HashMap<String, Vec<(semver::Version, VersionData)>> {
+	fn package_versions(&self, id: &str) -> Option<Vec<(semver::Version, VersionData)>> {
+		self.get(id).map(|versions| {
+			versions
+				.iter()
+				.map(|(version, data)| (version.clone(), data.clone()))
+				.collect()
+		})
+	}
+}
+
+pub struct IndexSource<'a> {
+	pub searcher: &'a Searcher,
+	pub package_schema: &'a PackageSchema,
+}
+
+impl PackageSource for IndexSource<'_> {
+	fn package_versions(&self, id: &str) -> Option<Vec<(semver::Version, VersionData)>> {
+		get_package_versions(self.searcher, self.package_schema, id)
+	}
+}
+
+/// A package version that got selected by the [Resolver].
+#[derive(Clone, Debug)]
+pub struct ResolvedPackage {
+	pub id: String,
+	pub version: semver::Version,
+	/// The package that caused this package to be selected, [None] for the requested package.
+	pub required_by: Option<String>,
+	pub provides: Vec<String>,
+	pub conflicts: Vec<String>,
+}
+
+impl ResolvedPackage {
+	/// Returns the conflict declaration of this package that excludes the other package, if any.
+	/// A declaration either names the other package directly or a capability it provides.
+	pub fn conflict_with(&self, other: &ResolvedPackage) -> Option<&String> {
+		self.conflicts
+			.iter()
+			.find(|c| **c == other.id || other.provides.contains(c))
+	}
+}
+
+#[derive(Clone, Debug)]
+pub enum ResolveError {
+	UnknownPackage {
+		id: String,
+		required_by: Option<String>,
+	},
+	NoCompatibleVersion {
+		id: String,
+		requirement: Option<semver::VersionReq>,
+		required_by: Option<String>,
+		rejected: Vec<(semver::Version, Incompatibility)>,
+	},
+	Conflict {
+		selected: Box<ResolvedPackage>,
+		requirement: semver::VersionReq,
+		required_by: Option<String>,
+	},
+	Exclusive {
+		package: Box<ResolvedPackage>,
+		excluded: Box<ResolvedPackage>,
+		declaration: String,
+	},
+	InvalidRequirement {
+		id: String,
+		requirement: String,
+		required_by: Option<String>,
+		error: String,
+	},
+}
+
+fn fmt_required_by(required_by: &Option<String>) -> String {
+	match required_by {
+		Some(by) => format!(" (required by '{by}')"),
+		None => String::new(),
+	}
+}
+
+impl Display for ResolveError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ResolveError::UnknownPackage { id, required_by } => {
+				write!(f, "package '{id}'{} does not exist", fmt_required_by(required_by))
+			}
+			ResolveError::NoCompatibleVersion {
+				id,
+				requirement,
+				required_by,
+				rejected,
+			} => {
+				write!(f, "no compatible version of package '{id}'")?;
+				if let Some(requirement) = requirement {
+					write!(f, " matching '{requirement}'")?;
+				}
+				write!(f, "{}", fmt_required_by(required_by))?;
+				for (version, reason) in rejected {
+					write!(f, "\n  v{version} {reason}")?;
+				}
+				Ok(())
+			}
+			ResolveError::Conflict {
+				selected,
+				requirement,
+				required_by,
+			} => write!(
+				f,
+				"package '{}' v{}{} does not match '{requirement}'{}",
+				selected.id,
+				selected.version,
+				fmt_required_by(&selected.required_by),
+				fmt_required_by(required_by),
+			),
+			ResolveError::Exclusive {
+				package,
+				excluded,
+				declaration,
+			} => write!(
+				f,
+				"package '{}' v{}{} conflicts with '{declaration}' of package '{}' v{}{}",
+				package.id,
+				package.version,
+				fmt_required_by(&package.required_by),
+				excluded.id,
+				excluded.version,
+				fmt_required_by(&excluded.required_by),
+			),
+			ResolveError::InvalidRequirement {
+				id,
+				requirement,
+				required_by,
+				error,
+			} => write!(
+				f,
+				"invalid version requirement '{requirement}' of package '{id}'{}: {error}",
+				fmt_required_by(required_by),
+			),
+		}
+	}
+}
+
+impl std::error::Error for ResolveError {}
+
+fn exclusion(selected: &[ResolvedPackage], package: &ResolvedPackage) -> Option<ResolveError> {
+	selected.iter().find_map(|other| {
+		let (package, excluded, declaration) =
+			if let Some(declaration) = package.conflict_with(other) {
+				(package, other, declaration)
+			} else {
+				(other, package, other.conflict_with(package)?)
+			};
+		Some(ResolveError::Exclusive {
+			package: Box::new(package.clone()),
+			excluded: Box::new(excluded.clone()),
+			declaration: declaration.clone(),
+		})
+	})
+}
+
+#[derive(Clone)]
+struct Pending {
+	id: String,
+	requirement: Option<semver::VersionReq>,
+	required_by: Option<String>,
+}
+
+/// Selects the newest compatible versions of a package and all of its transitive dependencies.
+///
+/// If a version can not be used, because its dependencies conflict with the already selected packages,
+/// the resolver backtracks to the next older version. In the worst case this tries every combination
+/// of versions of the involved packages, so the time grows with the product of their version counts.
+/// Dependencies that no version can satisfy do not depend on the selected packages,
+/// they are remembered to fail right away on the other branches.
+pub struct Resolver<'a, S: PackageSource> {
+	source: &'a S,
+	query: &'a QueryVersions,
+	/// The errors of the package ids and requirements no version satisfies.
+	unsatisfiable: RefCell<HashMap<(String, Option<semver::VersionReq>), ResolveError>>,
+}
+
+impl<'a, S: PackageSource> Resolver<'a, S> {
+	pub fn new(source: &'a S, query: &'a QueryVersions) -> Self {
+		Self {
+			source,
+			query,
+			unsatisfiable: RefCell::new(HashMap::new()),
+		}
+	}
+
+	/// Resolves the given package and its dependencies.
+	/// The requested package is the first entry of the result, followed by its dependencies.
+	pub fn resolve(
+		&self,
+		id: &str,
+		requirement: Option<semver::VersionReq>,
+	) -> Result<Vec<ResolvedPackage>, ResolveError> {
+		let pending = vec![Pending {
+			id: id.to_string(),
+			requirement,
+			required_by: None,
+		}];
+		self.step(Vec::new(), pending)
+	}
+
+	fn step(
+		&self,
+		selected: Vec<ResolvedPackage>,
+		mut pending: Vec<Pending>,
+	) -> Result<Vec<ResolvedPackage>, ResolveError> {
+		if pending.is_empty() {
+			return Ok(selected);
+		}
+		let next = pending.remove(0);
+
+		if let Some(package) = selected.iter().find(|p| p.id == next.id) {
+			return match &next.requirement {
+				Some(requirement)
+					if !matches_channel(requirement, &package.version, self.query.channel) =>
+				{
+					Err(ResolveError::Conflict {
+						selected: Box::new(package.clone()),
+						requirement: requirement.clone(),
+						required_by: next.required_by,
+					})
+				}
+				_ => self.step(selected, pending),
+			};
+		}
+
+		let key = (next.id.clone(), next.requirement.clone());
+		if let Some(error) = self.unsatisfiable.borrow().get(&key) {
+			return Err(error.clone());
+		}
+		let Some(mut versions) = self.source.package_versions(&next.id) else {
+			let error = ResolveError::UnknownPackage {
+				id: next.id,
+				required_by: next.required_by,
+			};
+			self.unsatisfiable.borrow_mut().insert(key, error.clone());
+			return Err(error);
+		};
+		versions.sort_by(|(v1, _), (v2, _)| v2.cmp(v1));
+
+		let mut rejected = Vec::new();
+		let mut error = None;
+		for (version, data) in versions {
+			if let Some(requirement) = &next.requirement {
+				if !matches_channel(requirement, &version, self.query.channel) {
+					continue;
+				}
+			}
+			if let Some(reason) = incompatibility(self.query, &data) {
+				rejected.push((version, reason));
+				continue;
+			}
+
+			let package = ResolvedPackage {
+				id: next.id.clone(),
+				version,
+				required_by: next.required_by.clone(),
+				provides: data.provides.clone(),
+				conflicts: data.conflicts.clone(),
+			};
+			if let Some(e) = exclusion(&selected, &package) {
+				error.get_or_insert(e);
+				continue;
+			}
+
+			let dependencies = data
+				.package_dependencies
+				.iter()
+				.map(|d| {
+					let requirement = d
+						.version
+						.as_deref()
+						.map(|v| {
+							parse_version_req_lenient(v)
+								.map(|r| r.value)
+								.map_err(|e| ResolveError::InvalidRequirement {
+									id: d.id.clone(),
+									requirement: v.to_string(),
+									required_by: Some(next.id.clone()),
+									error: e.to_string(),
+								})
+						})
+						.transpose()?;
+					Ok(Pending {
+						id: d.id.clone(),
+						requirement,
+						required_by: Some(next.id.clone()),
+					})
+				})
+				.collect::<Result<Vec<_>, _>>();
+			let dependencies = match dependencies {
+				Ok(dependencies) => dependencies,
+				Err(e) => {
+					error.get_or_insert(e);
+					continue;
+				}
+			};
+
+			let mut selected = selected.clone();
+			selected.push(package);
+			let mut pending = pending.clone();
+			pending.extend(dependencies);
+
+			match self.step(selected, pending) {
+				Ok(resolved) => return Ok(resolved),
+				Err(e) => {
+					error.get_or_insert(e);
+				}
+			}
+		}
+
+		match error {
+			Some(error) => Err(error),
+			None => {
+				let error = ResolveError::NoCompatibleVersion {
+					id: next.id,
+					requirement: next.requirement,
+					required_by: next.required_by,
+					rejected,
+				};
+				self.unsatisfiable.borrow_mut().insert(key, error.clone());
+				Err(error)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::compat::Channel;
+	use crate::index::PackageDependency;
+
+	type Source = HashMap<String, Vec<(semver::Version, VersionData)>>;
+
+	fn version(dependencies: &[(&str, Option<&str>)]) -> VersionData {
+		VersionData {
+			package_dependencies: dependencies
+				.iter()
+				.map(|(id, version)| PackageDependency {
+					id: id.to_string(),
+					version: version.map(str::to_string),
+				})
+				.collect(),
+			..Default::default()
+		}
+	}
+
+	fn source(packages: Vec<(&str, &str, VersionData)>) -> Source {
+		let mut source = Source::new();
+		for (id, version, data) in packages {
+			source
+				.entry(id.to_string())
+				.or_default()
+				.push((semver::Version::parse(version).unwrap(), data));
+		}
+		source
+	}
+
+	fn resolve(source: &Source, id: &str) -> Result<Vec<(String, String)>, ResolveError> {
+		resolve_on(source, id, Channel::Stable)
+	}
+
+	fn resolve_on(source: &Source, id: &str, channel: Channel) -> Result<Vec<(String, String)>, ResolveError> {
+		let query = QueryVersions {
+			fin_version: Some(semver::Version::new(0, 3, 19)),
+			channel,
+			..Default::default()
+		};
+		Resolver::new(source, &query).resolve(id, None).map(|resolved| {
+			resolved
+				.into_iter()
+				.map(|p| (p.id, p.version.to_string()))
+				.collect()
+		})
+	}
+
+	fn selected(packages: &[(&str, &str)]) -> Vec<(String, String)> {
+		packages.iter().map(|(id, version)| (id.to_string(), version.to_string())).collect()
+	}
+
+	#[test]
+	fn resolves_transitive_dependencies() {
+		let source = source(vec![
+			("App", "1.0.0", version(&[("Lib", Some(">=1.0"))])),
+			("Lib", "1.0.0", version(&[])),
+			("Lib", "2.0.0", version(&[("Base", None)])),
+			("Base", "0.1.0", version(&[])),
+		]);
+		assert_eq!(
+			resolve(&source, "App").unwrap(),
+			selected(&[("App", "1.0.0"), ("Lib", "2.0.0"), ("Base", "0.1.0")])
+		);
+	}
+
+	#[test]
+	fn backtracks_to_older_version() {
+		let source = source(vec![
+			("App", "1.0.0", version(&[("Lib", None), ("Base", Some("=1.0.0"))])),
+			("Lib", "1.0.0", version(&[("Base", Some(">=1.0"))])),
+			("Lib", "2.0.0", version(&[("Base", Some(">=2.0"))])),
+			("Base", "1.0.0", version(&[])),
+			("Base", "2.0.0", version(&[])),
+		]);
+		assert_eq!(
+			resolve(&source, "App").unwrap(),
+			selected(&[("App", "1.0.0"), ("Lib", "1.0.0"), ("Base", "1.0.0")])
+		);
+	}
+
+	#[test]
+	fn resolves_beta_dependencies_on_beta_channel() {
+		let source = source(vec![
+			("App", "1.0.0", version(&[("Lib", Some(">=1.0")), ("Tool", None)])),
+			("Tool", "1.0.0", version(&[("Lib", Some(">=1.0"))])),
+			(
+				"Lib",
+				"1.1.0-beta",
+				VersionData {
+					channel: Channel::Beta,
+					..version(&[])
+				},
+			),
+		]);
+		assert_eq!(
+			resolve_on(&source, "App", Channel::Beta).unwrap(),
+			selected(&[("App", "1.0.0"), ("Lib", "1.1.0-beta"), ("Tool", "1.0.0")])
+		);
+		assert!(matches!(
+			resolve_on(&source, "App", Channel::Stable),
+			Err(ResolveError::NoCompatibleVersion { id, .. }) if id == "Lib"
+		));
+	}
+
+	#[test]
+	fn reports_conflicting_requirements() {
+		let source = source(vec![
+			("App", "1.0.0", version(&[("Base", Some("=1.0.0")), ("Lib", None)])),
+			("Lib", "1.0.0", version(&[("Base", Some(">=2.0"))])),
+			("Base", "1.0.0", version(&[])),
+			("Base", "2.0.0", version(&[])),
+		]);
+		match resolve(&source, "App") {
+			Err(ResolveError::Conflict {
+				selected,
+				requirement,
+				required_by,
+			}) => {
+				assert_eq!((selected.id.as_str(), selected.version.to_string()), ("Base", "1.0.0".to_string()));
+				assert_eq!(requirement.to_string(), ">=2.0");
+				assert_eq!(required_by.as_deref(), Some("Lib"));
+			}
+			result => panic!("expected a conflict, got {result:?}"),
+		}
+	}
+
+	#[test]
+	fn excludes_conflicting_capabilities() {
+		let bootloader = |dependencies| VersionData {
+			provides: vec!["bootloader".to_string()],
+			conflicts: vec!["bootloader".to_string()],
+			..version(dependencies)
+		};
+		let source = source(vec![
+			("App", "1.0.0", version(&[("NetBoot", None), ("OtherBoot", None)])),
+			("NetBoot", "1.0.0", bootloader(&[])),
+			("OtherBoot", "1.0.0", bootloader(&[])),
+		]);
+		match resolve(&source, "App") {
+			Err(ResolveError::Exclusive {
+				package,
+				excluded,
+				declaration,
+			}) => {
+				assert_eq!(package.id, "OtherBoot");
+				assert_eq!(excluded.id, "NetBoot");
+				assert_eq!(declaration, "bootloader");
+			}
+			result => panic!("expected an exclusion, got {result:?}"),
+		}
+	}
+
+	#[test]
+	fn reports_unknown_packages() {
+		let source = source(vec![("App", "1.0.0", version(&[("Missing", None)]))]);
+		assert!(matches!(
+			resolve(&source, "App"),
+			Err(ResolveError::UnknownPackage { id, required_by: Some(by) }) if id == "Missing" && by == "App"
+		));
+		assert!(matches!(
+			resolve(&source, "Missing"),
+			Err(ResolveError::UnknownPackage { required_by: None, .. })
+		));
+	}
+
+	#[test]
+	fn reports_no_compatible_version() {
+		let source = source(vec![
+			("App", "1.0.0", version(&[("Lib", None)])),
+			(
+				"Lib",
+				"1.0.0",
+				VersionData {
+					fin_version: Some(">=0.4.0".to_string()),
+					..Default::default()
+				},
+			),
+		]);
+		match resolve(&source, "App") {
+			Err(ResolveError::NoCompatibleVersion { id, rejected, .. }) => {
+				assert_eq!(id, "Lib");
+				assert_eq!(rejected.len(), 1);
+				assert!(matches!(rejected[0].1, Incompatibility::FinVersion(_)));
+			}
+			result => panic!("expected no compatible version, got {result:?}"),
+		}
+	}
+
+	#[test]
+	fn reports_invalid_requirements() {
+		let source = source(vec![
+			("App", "1.0.0", version(&[("Lib", Some("newest please"))])),
+			("Lib", "1.0.0", version(&[])),
+		]);
+		assert!(matches!(
+			resolve(&source, "App"),
+			Err(ResolveError::InvalidRequirement { id, requirement, .. }) if id == "Lib" && requirement == "newest please"
+		));
+	}
+}
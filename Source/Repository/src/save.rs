@@ -0,0 +1,337 @@
+use crate::compat::QueryVersions;
+use crate::game::{release_by_build, GameRelease};
+use crate::smm::FIN_MOD_ID;
+use crate::util::parse_version_lenient;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// A mod listed in the mod metadata of a save.
+#[derive(Clone, Debug)]
+pub struct SaveMod {
+	pub reference: String,
+	pub name: Option<String>,
+	pub version: Option<semver::Version>,
+}
+
+/// The header at the start of a Satisfactory `.sav` file.
+#[derive(Clone, Debug)]
+pub struct SaveHeader {
+	pub header_version: i32,
+	pub save_version: i32,
+	/// The build number (changelist) of the game that wrote the save.
+	pub build_version: i32,
+	pub save_name: Option<String>,
+	pub map_name: String,
+	pub session_name: String,
+	pub play_duration_seconds: i32,
+	/// The mods written to the save by SML, [None] if the save has no mod metadata.
+	pub mods: Option<Vec<SaveMod>>,
+	pub is_modded: bool,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+	/// The data ends before the header is complete, more of the file is needed.
+	Incomplete,
+	Invalid(String),
+}
+
+impl Display for SaveError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SaveError::Incomplete => write!(f, "save file header is incomplete"),
+			SaveError::Invalid(e) => write!(f, "invalid save file header: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for SaveError {}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ModMetadata {
+	#[serde(default)]
+	mods: Vec<ModMetadataEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ModMetadataEntry {
+	reference: String,
+	name: Option<String>,
+	version: Option<String>,
+}
+
+/// The longest string we expect in a header, protects against reading garbage as huge strings.
+const MAX_STRING_LENGTH: usize = 1 << 20;
+
+struct HeaderReader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> HeaderReader<'a> {
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveError> {
+		if self.data.len() < len {
+			return Err(SaveError::Incomplete);
+		}
+		let (bytes, rest) = self.data.split_at(len);
+		self.data = rest;
+		Ok(bytes)
+	}
+
+	fn i32(&mut self) -> Result<i32, SaveError> {
+		Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+	}
+
+	fn i64(&mut self) -> Result<i64, SaveError> {
+		Ok(i64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+	}
+
+	/// Reads an Unreal `FString`: the length including the NUL terminator,
+	/// positive for single byte characters and negative for UTF-16 characters.
+	fn string(&mut self) -> Result<String, SaveError> {
+		let len = self.i32()?;
+		let chars = len.unsigned_abs() as usize;
+		if chars > MAX_STRING_LENGTH {
+			return Err(SaveError::Invalid(format!("string length {len} is too large")));
+		}
+		let string = if len >= 0 {
+			let bytes = self.bytes(chars)?;
+			String::from_utf8_lossy(bytes).into_owned()
+		} else {
+			let units: Vec<u16> = self
+				.bytes(chars * 2)?
+				.chunks_exact(2)
+				.map(|c| u16::from_le_bytes([c[0], c[1]]))
+				.collect();
+			String::from_utf16_lossy(&units)
+		};
+		Ok(string.trim_end_matches('\0').to_string())
+	}
+}
+
+/// Parses the header of a save file.
+/// Only the start of the file is needed, returns [SaveError::Incomplete] if the given data is too short.
+pub fn parse_save_header(data: &[u8]) -> Result<SaveHeader, SaveError> {
+	let mut reader = HeaderReader { data };
+	let header_version = reader.i32()?;
+	let save_version = reader.i32()?;
+	let build_version = reader.i32()?;
+	if !(1..100).contains(&header_version) || save_version < 0 || build_version < 0 {
+		return Err(SaveError::Invalid("not a Satisfactory save file".to_string()));
+	}
+	let save_name = if header_version >= 14 {
+		Some(reader.string()?)
+	} else {
+		None
+	};
+	let map_name = reader.string()?;
+	let _map_options = reader.string()?;
+	let session_name = reader.string()?;
+	let play_duration_seconds = reader.i32()?;
+	let _save_date_time = reader.i64()?;
+	if header_version >= 5 {
+		let _session_visibility = reader.bytes(1)?;
+	}
+	if header_version >= 7 {
+		let _editor_object_version = reader.i32()?;
+	}
+	let (mods, is_modded) = if header_version >= 8 {
+		let metadata = reader.string()?;
+		let is_modded = reader.i32()? != 0;
+		(parse_mod_metadata(&metadata)?, is_modded)
+	} else {
+		(None, false)
+	};
+
+	Ok(SaveHeader {
+		header_version,
+		save_version,
+		build_version,
+		save_name,
+		map_name,
+		session_name,
+		play_duration_seconds,
+		mods,
+		is_modded,
+	})
+}
+
+fn parse_mod_metadata(metadata: &str) -> Result<Option<Vec<SaveMod>>, SaveError> {
+	if metadata.trim().is_empty() {
+		return Ok(None);
+	}
+	let metadata: ModMetadata = serde_json::from_str(metadata)
+		.map_err(|e| SaveError::Invalid(format!("invalid mod metadata: {e}")))?;
+	Ok(Some(
+		metadata
+			.mods
+			.into_iter()
+			.map(|m| SaveMod {
+				version: m
+					.version
+					.as_deref()
+					.and_then(|v| parse_version_lenient(v).ok())
+					.map(|v| v.value),
+				reference: m.reference,
+				name: m.name,
+			})
+			.collect(),
+	))
+}
+
+impl SaveHeader {
+	/// Returns the game release the save was written by.
+	pub fn game_release(&self) -> Option<&'static GameRelease> {
+		release_by_build(self.build_version as u64)
+	}
+
+	pub fn fin_version(&self) -> Option<semver::Version> {
+		self.mods
+			.iter()
+			.flatten()
+			.find(|m| m.reference == FIN_MOD_ID)
+			.and_then(|m| m.version.clone())
+	}
+
+	/// Mods are only checked if the save contains mod metadata.
+	pub fn query_versions(&self) -> QueryVersions {
+		let mods: HashMap<_, _> = self
+			.mods
+			.iter()
+			.flatten()
+			.map(|m| (m.reference.clone(), m.version.clone()))
+			.collect();
+		QueryVersions {
+			fin_version: self.fin_version(),
+			game_version: self.game_release().map(|r| r.version.clone()),
+			check_mods: self.mods.is_some(),
+			mods,
+			..Default::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Writes a header in the layout of the game, from the header version up to the modded flag.
+	struct HeaderWriter(Vec<u8>);
+
+	impl HeaderWriter {
+		fn u8(mut self, v: u8) -> Self {
+			self.0.push(v);
+			self
+		}
+
+		fn i32(mut self, v: i32) -> Self {
+			self.0.extend(v.to_le_bytes());
+			self
+		}
+
+		fn i64(mut self, v: i64) -> Self {
+			self.0.extend(v.to_le_bytes());
+			self
+		}
+
+		fn string(mut self, s: &str) -> Self {
+			if s.is_ascii() {
+				self = self.i32(s.len() as i32 + 1);
+				self.0.extend(s.as_bytes());
+				self.0.push(0);
+			} else {
+				let units: Vec<u16> = s.encode_utf16().chain([0]).collect();
+				self = self.i32(-(units.len() as i32));
+				self.0.extend(units.iter().flat_map(|u| u.to_le_bytes()));
+			}
+			self
+		}
+	}
+
+	const MOD_METADATA: &str = r#"{"Version":1,"FullMapName":"/Game/FactoryGame/Map/GameLevel01/Persistent_Level","Mods":[{"Reference":"SML","Name":"Satisfactory Mod Loader","Version":"3.8.0"},{"Reference":"FicsItNetworks","Name":"FicsIt-Networks","Version":"0.3.23"},{"Reference":"PowerSuit","Name":"Power Suit"}]}"#;
+
+	/// A header as written by the 1.0 release with FIN installed.
+	fn modded_header() -> Vec<u8> {
+		HeaderWriter(Vec::new())
+			.i32(14)
+			.i32(46)
+			.i32(365306)
+			.string("Fabrik")
+			.string("Persistent_Level")
+			.string("?startloc=Grass Fields?sessionName=Fabrik Süd?Visibility=SV_FriendsOnly")
+			.string("Fabrik Süd")
+			.i32(7265)
+			.i64(638_600_000_000_000_000)
+			.u8(1)
+			.i32(0)
+			.string(MOD_METADATA)
+			.i32(1)
+			.0
+	}
+
+	#[test]
+	fn parses_modded_header() {
+		let header = parse_save_header(&modded_header()).unwrap();
+		assert_eq!((header.header_version, header.save_version, header.build_version), (14, 46, 365306));
+		assert_eq!(header.save_name.as_deref(), Some("Fabrik"));
+		assert_eq!(header.map_name, "Persistent_Level");
+		assert_eq!(header.session_name, "Fabrik Süd");
+		assert_eq!(header.play_duration_seconds, 7265);
+		assert!(header.is_modded);
+		assert_eq!(header.game_release().unwrap().name, "1.0");
+		assert_eq!(header.fin_version(), Some(semver::Version::new(0, 3, 23)));
+
+		let query = header.query_versions();
+		assert!(query.check_mods);
+		assert_eq!(query.game_version, Some(semver::Version::new(1, 0, 0)));
+		assert_eq!(query.mods.get("SML"), Some(&Some(semver::Version::new(3, 8, 0))));
+		assert_eq!(query.mods.get("PowerSuit"), Some(&None));
+	}
+
+	#[test]
+	fn parses_unmodded_header() {
+		let data = HeaderWriter(Vec::new())
+			.i32(8)
+			.i32(25)
+			.i32(152331)
+			.string("Persistent_Level")
+			.string("")
+			.string("Old Save")
+			.i32(60)
+			.i64(0)
+			.u8(0)
+			.i32(0)
+			.string("")
+			.i32(0)
+			.0;
+		let header = parse_save_header(&data).unwrap();
+		assert_eq!(header.save_name, None);
+		assert_eq!(header.session_name, "Old Save");
+		assert!(header.mods.is_none());
+		assert_eq!(header.game_release().unwrap().name, "Update 5");
+		assert!(!header.query_versions().check_mods);
+	}
+
+	#[test]
+	fn needs_the_whole_header() {
+		let data = modded_header();
+		for len in 0..data.len() {
+			assert!(
+				matches!(parse_save_header(&data[..len]), Err(SaveError::Incomplete)),
+				"header truncated to {len} bytes"
+			);
+		}
+		let mut data = data;
+		data.extend([0; 64]);
+		assert!(parse_save_header(&data).is_ok());
+	}
+
+	#[test]
+	fn rejects_other_files() {
+		assert!(matches!(parse_save_header(b"PK\x03\x04\x14\x00\x00\x00\x08\x00\x00\x00"), Err(SaveError::Invalid(_))));
+		let huge_string = HeaderWriter(Vec::new()).i32(14).i32(46).i32(365306).i32(i32::MAX).0;
+		assert!(matches!(parse_save_header(&huge_string), Err(SaveError::Invalid(_))));
+	}
+}
@@ -0,0 +1,1699 @@
+use crate::compat::{check_versions, latest_compatible_version, QueryVersions};
+use crate::index::{
+	read_package_versions, PackageSchema, VersionData, FACET_AUTHORS, FACET_FIN, FACET_MODS,
+	FACET_TAGS, KIND_EEPROM, KIND_PACKAGE,
+};
+use crate::util::serialize_semver;
+use levenshtein_automata::{Distance, LevenshteinAutomatonBuilder, DFA};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tantivy::collector::{BytesFilterCollector, Collector, Count, FacetCollector, FacetCounts, TopDocs};
+use tantivy::query::{
+	AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, MoreLikeThisQuery, Occur, Query, QueryParser,
+	QueryParserError, TermQuery, TermSetQuery,
+};
+use tantivy::query_grammar::{UserInputAst, UserInputLeaf};
+use tantivy::schema::{Facet, IndexRecordOption, TantivyDocument, Value};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{
+	query_grammar, DocAddress, DocId, Score, Searcher, SegmentId, SegmentReader, TantivyError, Term,
+};
+use tantivy_fst::Automaton;
+
+/// The order of search results.
+/// Relevance falls back to name if there is nothing to rank by, like for an empty query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sort {
+	#[default]
+	Relevance,
+	Name,
+	/// Newest first release first.
+	Newest,
+	/// Most recently updated first.
+	Updated,
+	/// Most dependent packages first.
+	Popular,
+}
+
+impl Sort {
+	pub const ALL: [Sort; 5] = [Sort::Relevance, Sort::Name, Sort::Newest, Sort::Updated, Sort::Popular];
+}
+
+impl Display for Sort {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Sort::Relevance => f.write_str("relevance"),
+			Sort::Name => f.write_str("name"),
+			Sort::Newest => f.write_str("newest"),
+			Sort::Updated => f.write_str("updated"),
+			Sort::Popular => f.write_str("popular"),
+		}
+	}
+}
+
+impl FromStr for Sort {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"relevance" => Ok(Sort::Relevance),
+			"name" => Ok(Sort::Name),
+			"newest" => Ok(Sort::Newest),
+			"updated" => Ok(Sort::Updated),
+			"popular" => Ok(Sort::Popular),
+			_ => Err(format!(
+				"unknown sort '{s}', expected 'relevance', 'name', 'newest', 'updated' or 'popular'"
+			)),
+		}
+	}
+}
+
+/// How much matches in each field of a package count, names count more than readmes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldBoosts {
+	pub id: f32,
+	pub name: f32,
+	pub tags: f32,
+	pub authors: f32,
+	pub short_description: f32,
+	pub readme: f32,
+	pub versions: f32,
+}
+
+impl Default for FieldBoosts {
+	fn default() -> Self {
+		Self {
+			id: 3.0,
+			name: 3.0,
+			tags: 2.0,
+			authors: 1.5,
+			short_description: 1.5,
+			readme: 1.0,
+			versions: 1.0,
+		}
+	}
+}
+
+impl FieldBoosts {
+	/// Sets the boost of the field with the given name.
+	pub fn set(&mut self, field: &str, boost: f32) -> Result<(), String> {
+		let value = match field {
+			"id" => &mut self.id,
+			"name" => &mut self.name,
+			"tags" => &mut self.tags,
+			"authors" => &mut self.authors,
+			"short_description" => &mut self.short_description,
+			"readme" => &mut self.readme,
+			"versions" => &mut self.versions,
+			_ => {
+				return Err(format!(
+					"unknown field '{field}', expected 'id', 'name', 'tags', 'authors', 'short_description', 'readme' or 'versions'"
+				))
+			}
+		};
+		*value = boost;
+		Ok(())
+	}
+}
+
+/// The maximum number of hits of a page.
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// The maximum number of hits a request can skip with its offset, later pages need a [Cursor].
+/// The collector keeps all skipped hits in memory, so the offset has to be limited.
+pub const MAX_OFFSET: usize = 10_000;
+
+/// The position of the last hit of a page, the next page starts after it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cursor {
+	/// The order the cursor belongs to, cursors of other orders are ignored.
+	pub sort: Sort,
+	/// The value the hits are sorted by, like the score.
+	/// Written as the hex of its bits, which is short and exact even for keys like [f64::MIN].
+	pub key: f64,
+	pub address: DocAddress,
+}
+
+impl Cursor {
+	/// Whether the hit with the given sort key and address comes after the cursor.
+	fn precedes(&self, key: f64, address: DocAddress) -> bool {
+		key < self.key || (key == self.key && address > self.address)
+	}
+}
+
+impl Display for Cursor {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{}_{:x}_{}_{}",
+			self.sort,
+			self.key.to_bits(),
+			self.address.segment_ord,
+			self.address.doc_id
+		)
+	}
+}
+
+impl FromStr for Cursor {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("invalid cursor '{s}'");
+		let mut parts = s.split('_');
+		let (Some(sort), Some(key), Some(segment_ord), Some(doc_id), None) =
+			(parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+		else {
+			return Err(invalid());
+		};
+		Ok(Cursor {
+			sort: sort.parse()?,
+			key: u64::from_str_radix(key, 16)
+				.map(f64::from_bits)
+				.map_err(|_| invalid())?,
+			address: DocAddress::new(
+				segment_ord.parse().map_err(|_| invalid())?,
+				doc_id.parse().map_err(|_| invalid())?,
+			),
+		})
+	}
+}
+
+/// A package search, filtered by the compatibility of the package versions.
+#[derive(Clone)]
+pub struct SearchRequest {
+	/// The query in tantivy query syntax, an empty query matches all packages.
+	pub query: String,
+	pub versions: QueryVersions,
+	/// Only packages providing this capability.
+	pub provides: Option<String>,
+	/// Only packages having all of these facets.
+	pub facets: Vec<Facet>,
+	pub sort: Sort,
+	/// The number of hits per page, at most [MAX_PAGE_SIZE].
+	pub limit: usize,
+	/// The number of hits to skip, at most [MAX_OFFSET], ignored if there is a cursor.
+	pub offset: usize,
+	/// Continues after the last hit of a previous page, stays stable even if hits have the same score.
+	pub cursor: Option<Cursor>,
+	/// Also matches misspelled words of the query and words starting with a word of the query.
+	pub fuzzy: bool,
+	pub boosts: FieldBoosts,
+	/// Adds an explanation of the score to every hit.
+	pub explain: bool,
+	/// Ignores the invalid parts of the query instead of failing, see [SearchResult::query_errors].
+	pub lenient: bool,
+}
+
+impl Default for SearchRequest {
+	fn default() -> Self {
+		Self {
+			query: String::new(),
+			versions: QueryVersions::default(),
+			provides: None,
+			facets: Vec::new(),
+			sort: Sort::Relevance,
+			limit: 10,
+			offset: 0,
+			cursor: None,
+			fuzzy: true,
+			boosts: FieldBoosts::default(),
+			explain: false,
+			lenient: false,
+		}
+	}
+}
+
+pub struct SearchHit {
+	pub id: String,
+	/// The relevance score, only if sorted by relevance.
+	pub score: Option<f32>,
+	/// The newest version compatible with the request, the one the package matched with.
+	pub version: Option<semver::Version>,
+	pub versions: Vec<(semver::Version, VersionData)>,
+	/// The score explanation as pretty JSON, only if requested.
+	pub explanation: Option<String>,
+	/// Where the query matched the description or readme, [None] for an empty query or matches elsewhere.
+	pub highlight: Option<Highlight>,
+	/// The EEPROMs of the package matching the query, best first, each file only of its newest compatible version.
+	pub eeproms: Vec<EepromHit>,
+}
+
+/// An EEPROM matching the query by its title, description or code.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EepromHit {
+	pub package: String,
+	#[serde(serialize_with = "serialize_semver")]
+	pub version: semver::Version,
+	/// The file name of the EEPROM in the version folder.
+	pub file: String,
+	pub title: String,
+	/// The FIN APIs and classes the EEPROM uses, see [crate::lua::requirements].
+	pub requirements: Vec<String>,
+	pub score: f32,
+	/// Where the query matched the description or code.
+	pub highlight: Option<Highlight>,
+}
+
+/// A part of a package text matching the query.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Highlight {
+	/// The name of the matching field, `short_description` or `readme`.
+	pub field: String,
+	pub fragment: String,
+	/// The fragment HTML escaped, with the matching words in `<b>` tags.
+	pub html: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FacetCount {
+	pub value: String,
+	/// The full facet path, like `/tags/power`, to filter by it with [SearchRequest::facets].
+	pub facet: String,
+	pub count: usize,
+}
+
+/// How many of the matching packages have each facet value, most common first.
+#[derive(Default, Serialize)]
+pub struct SearchFacets {
+	pub tags: Vec<FacetCount>,
+	pub authors: Vec<FacetCount>,
+	/// The mods required by at least one version of the package.
+	pub mods: Vec<FacetCount>,
+	/// The minimum FIN versions (`major.minor`) required by the versions of the package.
+	pub fin_versions: Vec<FacetCount>,
+}
+
+pub struct SearchResult {
+	pub hits: Vec<SearchHit>,
+	/// The number of all matching packages, not only of this page.
+	pub total: usize,
+	pub facets: SearchFacets,
+	/// A spelling of the query with known words, if the query as written matched nothing.
+	pub suggestion: Option<String>,
+	/// The problems of the query that got ignored by a lenient search.
+	pub query_errors: Option<InvalidQuery>,
+	/// Whether there are more hits after this page.
+	pub has_more: bool,
+	/// Continues with the next page, if there is one.
+	pub next_cursor: Option<Cursor>,
+}
+
+/// A problem in the query, like an unclosed quote or an unknown field.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryError {
+	pub message: String,
+	/// The byte offset in the query the problem was found at, if known.
+	pub position: Option<usize>,
+}
+
+/// All problems of a query, together with a corrected query if one could be found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvalidQuery {
+	pub query: String,
+	pub errors: Vec<QueryError>,
+	pub suggestion: Option<String>,
+}
+
+impl Display for InvalidQuery {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		for (i, error) in self.errors.iter().enumerate() {
+			if i > 0 {
+				f.write_str(", ")?;
+			}
+			f.write_str(&error.message)?;
+			if let Some(position) = error.position {
+				write!(f, " at position {position}")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug)]
+pub enum SearchError {
+	Query(InvalidQuery),
+	/// The offset of the request is larger than [MAX_OFFSET].
+	Offset(usize),
+	Index(TantivyError),
+}
+
+impl Display for SearchError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SearchError::Query(e) => write!(f, "invalid query: {e}"),
+			SearchError::Offset(offset) => write!(
+				f,
+				"offset {offset} is too large, at most {MAX_OFFSET} hits can be skipped, continue with a cursor instead"
+			),
+			SearchError::Index(e) => write!(f, "search failed: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<InvalidQuery> for SearchError {
+	fn from(e: InvalidQuery) -> Self {
+		SearchError::Query(e)
+	}
+}
+
+impl From<TantivyError> for SearchError {
+	fn from(e: TantivyError) -> Self {
+		SearchError::Index(e)
+	}
+}
+
+/// Wraps the collector so it only sees packages with at least one version compatible with the given [QueryVersions].
+/// Versions whose data can not be decoded, because the index was written by another version of this crate, are skipped.
+pub fn version_filter<C: Collector>(
+	versions: &QueryVersions,
+	collector: C,
+) -> BytesFilterCollector<C, impl Fn(&[u8]) -> bool + Send + Sync + Clone + 'static> {
+	let versions = versions.clone();
+	BytesFilterCollector::new(
+		"version_data".to_string(),
+		move |bytes: &[u8]| {
+			bitcode::decode::<VersionData>(bytes)
+				.is_ok_and(|version_data| check_versions(&versions, &version_data).is_some())
+		},
+		collector,
+	)
+}
+
+/// The maximum length of a [Highlight] fragment.
+const MAX_HIGHLIGHT_CHARS: usize = 200;
+
+/// Words shorter than this are not matched as prefix, they would match too many words.
+const MIN_PREFIX_LENGTH: usize = 3;
+
+/// The edit distance tolerated for a misspelled word, short words have to match exactly.
+fn fuzzy_distance(word: &str) -> u8 {
+	match word.chars().count() {
+		0..=3 => 0,
+		4..=6 => 1,
+		_ => 2,
+	}
+}
+
+/// The optimal string alignment distance, like the Levenshtein distance but transpositions count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let a: Vec<char> = a.chars().collect();
+	let b: Vec<char> = b.chars().collect();
+	let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for (i, row) in distances.iter_mut().enumerate() {
+		row[0] = i;
+	}
+	for (j, distance) in distances[0].iter_mut().enumerate() {
+		*distance = j;
+	}
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			let cost = usize::from(a[i - 1] != b[j - 1]);
+			let mut distance = (distances[i - 1][j] + 1)
+				.min(distances[i][j - 1] + 1)
+				.min(distances[i - 1][j - 1] + cost);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				distance = distance.min(distances[i - 2][j - 2] + 1);
+			}
+			distances[i][j] = distance;
+		}
+	}
+	distances[a.len()][b.len()]
+}
+
+/// Splits the query into the words of the spelling dictionary, CamelCase words are kept as a whole.
+/// Queries using query syntax are not split, they are meant to be taken literally.
+fn query_words(searcher: &Searcher, schema: &PackageSchema, query: &str) -> Vec<String> {
+	if query.contains([':', '"', '(', '[', '{', '^', '*', '~']) {
+		return Vec::new();
+	}
+	let Ok(mut tokenizer) = searcher.index().tokenizer_for_field(schema.spelling) else {
+		return Vec::new();
+	};
+	let mut words = Vec::new();
+	let mut tokens = tokenizer.token_stream(query);
+	let mut end = 0;
+	while tokens.advance() {
+		let token = tokens.token();
+		if token.offset_from >= end {
+			end = token.offset_to;
+			words.push(token.text.clone());
+		}
+	}
+	words
+}
+
+fn query_parser(searcher: &Searcher, schema: &PackageSchema, boosts: &FieldBoosts) -> QueryParser {
+	let boosts = [
+		(schema.id, boosts.id),
+		(schema.id_text, boosts.id),
+		(schema.name, boosts.name),
+		(schema.short_description, boosts.short_description),
+		(schema.readme, boosts.readme),
+		(schema.tags, boosts.tags),
+		(schema.versions, boosts.versions),
+		(schema.authors, boosts.authors),
+	];
+	let mut query_parser =
+		QueryParser::for_index(searcher.index(), boosts.iter().map(|(field, _)| *field).collect());
+	for (field, boost) in boosts {
+		query_parser.set_field_boost(field, boost);
+	}
+	query_parser
+}
+
+/// Whether the query only has excluding clauses, like `-requires:gpu`.
+fn only_excludes(ast: &UserInputAst) -> bool {
+	match ast {
+		UserInputAst::Clause(clauses) => {
+			!clauses.is_empty()
+				&& clauses
+					.iter()
+					.all(|(occur, _)| *occur == Some(query_grammar::Occur::MustNot))
+		}
+		_ => false,
+	}
+}
+
+/// Parses the query leniently, invalid parts of the query are left out and reported as errors.
+/// A query of only excluding clauses matches all other documents.
+fn parse_query(query_parser: &QueryParser, query: &str) -> (Box<dyn Query>, Vec<QueryError>) {
+	let (mut ast, syntax_errors) = query_grammar::parse_query_lenient(query);
+	if only_excludes(&ast) {
+		if let UserInputAst::Clause(clauses) = &mut ast {
+			clauses.push((
+				Some(query_grammar::Occur::Should),
+				UserInputAst::Leaf(Box::new(UserInputLeaf::All)),
+			));
+		}
+	}
+	let mut errors: Vec<_> = syntax_errors
+		.into_iter()
+		.map(|e| QueryError {
+			message: e.message,
+			position: Some(e.pos),
+		})
+		.collect();
+	let (parsed, field_errors) = query_parser.build_query_from_user_input_ast_lenient(ast);
+	errors.extend(field_errors.into_iter().map(|e| {
+		let position = match &e {
+			QueryParserError::FieldDoesNotExist(field) | QueryParserError::FieldNotIndexed(field) => {
+				query.find(&format!("{field}:"))
+			}
+			_ => None,
+		};
+		QueryError {
+			message: e.to_string(),
+			position,
+		}
+	}));
+	(parsed, errors)
+}
+
+/// Tries to fix the query: closes quotes, parentheses and ranges, drops unmatched closing ones,
+/// drops dangling operators, colons and boosts and replaces unknown fields by the most similar field, or drops them.
+fn fix_query(searcher: &Searcher, query: &str, errors: &[QueryError]) -> String {
+	let mut fixed = String::new();
+	let mut closers: Vec<char> = Vec::new();
+	let mut quoted = false;
+	let mut chars = query.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' => {
+				fixed.push(c);
+				fixed.extend(chars.next());
+				continue;
+			}
+			'"' => quoted = !quoted,
+			_ if quoted => {}
+			'(' => closers.push(')'),
+			'[' | '{' => closers.push(']'),
+			')' if closers.last() == Some(&')') => {
+				closers.pop();
+			}
+			']' | '}' if closers.last() == Some(&']') => {
+				closers.pop();
+			}
+			')' | ']' | '}' => continue,
+			_ => {}
+		}
+		fixed.push(c);
+	}
+	if quoted {
+		fixed.push('"');
+	}
+	fixed.extend(closers.iter().rev());
+
+	let words: Vec<&str> = fixed
+		.split_whitespace()
+		.map(|word| word.trim_end_matches([':', '^']))
+		.filter(|word| !word.is_empty())
+		.collect();
+	let start = words.iter().take_while(|w| matches!(**w, "AND" | "OR")).count();
+	let end = words.len()
+		- words[start..]
+			.iter()
+			.rev()
+			.take_while(|w| matches!(**w, "AND" | "OR" | "NOT" | "+" | "-"))
+			.count();
+	let mut fixed = words[start..end].join(" ");
+
+	let schema = searcher.schema();
+	let fields: Vec<&str> = schema
+		.fields()
+		.filter(|(_, entry)| entry.is_indexed())
+		.map(|(_, entry)| entry.name())
+		.collect();
+	for error in errors {
+		let Some(field) = error
+			.position
+			.and_then(|position| query[position..].split_once(':'))
+			.map(|(field, _)| field)
+			.filter(|field| !field.is_empty() && field.chars().all(|c| c.is_alphanumeric() || c == '_'))
+		else {
+			continue;
+		};
+		if fields.contains(&field) {
+			continue;
+		}
+		let replacement = fields
+			.iter()
+			.map(|candidate| (edit_distance(field, candidate), *candidate))
+			.filter(|(distance, _)| *distance <= 2)
+			.min()
+			.map(|(_, candidate)| format!("{candidate}:"))
+			.unwrap_or_default();
+		fixed = fixed.replacen(&format!("{field}:"), &replacement, 1);
+	}
+	fixed
+}
+
+/// Checks the query for syntax errors and unknown fields, [None] if the query is valid.
+pub fn check_query(searcher: &Searcher, schema: &PackageSchema, query: &str) -> Option<InvalidQuery> {
+	let query_parser = query_parser(searcher, schema, &FieldBoosts::default());
+	let (_, errors) = parse_query(&query_parser, query);
+	if errors.is_empty() {
+		return None;
+	}
+	let suggestion = Some(fix_query(searcher, query, &errors))
+		.filter(|fixed| !fixed.trim().is_empty() && fixed != query && parse_query(&query_parser, fixed).1.is_empty());
+	Some(InvalidQuery {
+		query: query.to_string(),
+		errors,
+		suggestion,
+	})
+}
+
+/// Builds the query of the request, if fuzzy is set words may also match misspelled or as prefix.
+/// The packages with the given ids match too, like the ones with EEPROMs matching the query.
+/// Invalid parts of the query are left out, see [check_query].
+pub fn build_query(
+	searcher: &Searcher,
+	schema: &PackageSchema,
+	request: &SearchRequest,
+	fuzzy: bool,
+	packages: &[String],
+) -> Box<dyn Query> {
+	let query_parser = query_parser(searcher, schema, &request.boosts);
+	let mut query = match request.query.trim() {
+		"" => Box::new(AllQuery),
+		query => parse_query(&query_parser, query).0,
+	};
+
+	let fuzzy_words: Vec<Box<dyn Query>> = if fuzzy {
+		query_words(searcher, schema, &request.query)
+			.into_iter()
+			.filter(|word| word.chars().count() >= MIN_PREFIX_LENGTH)
+			.flat_map(|word| {
+				let term = Term::from_field_text(schema.spelling, &word);
+				let prefix: Box<dyn Query> = Box::new(FuzzyTermQuery::new_prefix(term.clone(), 0, true));
+				let distance = fuzzy_distance(&word);
+				let misspelled: Option<Box<dyn Query>> =
+					(distance > 0).then(|| Box::new(FuzzyTermQuery::new(term, distance, true)) as _);
+				std::iter::once(prefix).chain(misspelled)
+			})
+			.collect()
+	} else {
+		Vec::new()
+	};
+	if !fuzzy_words.is_empty() {
+		// fuzzy and prefix matches score constant, they are weighted down to rank exact matches first
+		let fuzzy: Box<dyn Query> = Box::new(BoostQuery::new(
+			Box::new(BooleanQuery::new(
+				fuzzy_words.into_iter().map(|q| (Occur::Should, q)).collect(),
+			)),
+			0.5,
+		));
+		query = Box::new(BooleanQuery::new(vec![(Occur::Should, query), (Occur::Should, fuzzy)]));
+	}
+
+	if !packages.is_empty() {
+		let ids = packages.iter().map(|id| Term::from_field_text(schema.id, id));
+		let packages: Box<dyn Query> = Box::new(BoostQuery::new(Box::new(TermSetQuery::new(ids)), 0.5));
+		query = Box::new(BooleanQuery::new(vec![(Occur::Should, query), (Occur::Should, packages)]));
+	}
+
+	let mut filters: Vec<Term> = request
+		.facets
+		.iter()
+		.map(|facet| Term::from_facet(schema.facets, facet))
+		.collect();
+	if let Some(capability) = request.provides.as_deref().filter(|s| !s.is_empty()) {
+		filters.push(Term::from_field_text(schema.provides, capability));
+	}
+	filters.push(Term::from_field_text(schema.kind, KIND_PACKAGE));
+	let mut clauses = vec![(Occur::Must, query)];
+	clauses.extend(filters.into_iter().map(|term| {
+		let query: Box<dyn Query> = Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+		(Occur::Must, query)
+	}));
+	Box::new(BooleanQuery::new(clauses))
+}
+
+/// The maximum number of EEPROMs matching a query, more would only add packages with worse matches.
+const MAX_EEPROM_HITS: usize = 1000;
+
+/// Returns the highlight of the first field of the highlighters the document matched in.
+fn highlight(highlighters: &[(&str, SnippetGenerator)], doc: &TantivyDocument) -> Option<Highlight> {
+	highlighters
+		.iter()
+		.map(|(name, generator)| (name, generator.snippet_from_doc(doc)))
+		.find(|(_, snippet)| !snippet.is_empty())
+		.map(|(name, snippet)| Highlight {
+			field: name.to_string(),
+			fragment: snippet.fragment().to_string(),
+			html: snippet.to_html(),
+		})
+}
+
+/// Searches the EEPROMs of versions compatible with the request, best first.
+fn search_eeproms(
+	searcher: &Searcher,
+	schema: &PackageSchema,
+	request: &SearchRequest,
+) -> tantivy::Result<Vec<EepromHit>> {
+	let mut query_parser = QueryParser::for_index(
+		searcher.index(),
+		vec![schema.eeprom_title, schema.eeprom_description, schema.eeprom_code],
+	);
+	query_parser.set_field_boost(schema.eeprom_title, 2.0);
+	query_parser.set_field_boost(schema.eeprom_description, 1.5);
+	let (query, _) = parse_query(&query_parser, request.query.trim());
+	let query = BooleanQuery::new(vec![
+		(Occur::Must, query),
+		(
+			Occur::Must,
+			Box::new(TermQuery::new(
+				Term::from_field_text(schema.kind, KIND_EEPROM),
+				IndexRecordOption::Basic,
+			)),
+		),
+	]);
+
+	let top_docs = searcher.search(
+		&query,
+		&version_filter(&request.versions, TopDocs::with_limit(MAX_EEPROM_HITS)),
+	)?;
+	let highlighters = [("description", schema.eeprom_description), ("code", schema.eeprom_code)]
+		.into_iter()
+		.map(|(name, field)| {
+			let mut generator = SnippetGenerator::create(searcher, &query, field)?;
+			generator.set_max_num_chars(MAX_HIGHLIGHT_CHARS);
+			Ok((name, generator))
+		})
+		.collect::<tantivy::Result<Vec<_>>>()?;
+
+	let mut hits = Vec::new();
+	for (score, address) in top_docs {
+		let doc: TantivyDocument = searcher.doc(address)?;
+		let text = |field| {
+			doc.get_first(field)
+				.and_then(|v| v.as_str())
+				.unwrap_or_default()
+				.to_string()
+		};
+		let Ok(version) = semver::Version::parse(&text(schema.eeprom_version)) else {
+			continue;
+		};
+		hits.push(EepromHit {
+			package: text(schema.eeprom_package),
+			version,
+			file: text(schema.eeprom_file),
+			title: text(schema.eeprom_title),
+			requirements: doc
+				.get_all(schema.eeprom_requirements)
+				.filter_map(|v| v.as_str())
+				.map(str::to_string)
+				.collect(),
+			score,
+			highlight: highlight(&highlighters, &doc),
+		});
+	}
+	Ok(hits)
+}
+
+/// Returns the counts of the children of the given facet root, most common first.
+fn facet_counts(counts: &FacetCounts, root: &str) -> Vec<FacetCount> {
+	let mut counts: Vec<_> = counts
+		.get(Facet::from_path([root]))
+		.map(|(facet, count)| FacetCount {
+			value: facet.to_path().last().unwrap_or(&"").to_string(),
+			facet: facet.to_string(),
+			count: count as usize,
+		})
+		.collect();
+	counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+	counts
+}
+
+/// Matches the terms of a term dictionary within the edit distance of a [DFA].
+struct SpellingAutomaton(DFA);
+
+impl Automaton for SpellingAutomaton {
+	type State = u32;
+
+	fn start(&self) -> u32 {
+		self.0.initial_state()
+	}
+
+	fn is_match(&self, state: &u32) -> bool {
+		matches!(self.0.distance(*state), Distance::Exact(_))
+	}
+
+	fn can_match(&self, state: &u32) -> bool {
+		*state != levenshtein_automata::SINK_STATE
+	}
+
+	fn accept(&self, state: &u32, byte: u8) -> u32 {
+		self.0.transition(*state, byte)
+	}
+}
+
+/// Builds the automaton of the words within the given edit distance of a word, transpositions count as one edit.
+fn spelling_automaton(word: &str, distance: u8) -> SpellingAutomaton {
+	static BUILDERS: OnceLock<[LevenshteinAutomatonBuilder; 2]> = OnceLock::new();
+	let builders = BUILDERS.get_or_init(|| {
+		[
+			LevenshteinAutomatonBuilder::new(1, true),
+			LevenshteinAutomatonBuilder::new(2, true),
+		]
+	});
+	SpellingAutomaton(builders[distance.clamp(1, 2) as usize - 1].build_dfa(word))
+}
+
+/// Suggests a spelling of the query using only words known to the index, [None] if all words are known.
+/// Candidates are within the [fuzzy_distance] of a word, the closest and then most common one wins.
+pub fn suggest_spelling(searcher: &Searcher, schema: &PackageSchema, query: &str) -> Option<String> {
+	let words = query_words(searcher, schema, query);
+	if words.is_empty() {
+		return None;
+	}
+	let inverted_indices = searcher
+		.segment_readers()
+		.iter()
+		.map(|segment| segment.inverted_index(schema.spelling))
+		.collect::<tantivy::Result<Vec<_>>>()
+		.ok()?;
+
+	let mut changed = false;
+	let mut suggestion = Vec::new();
+	for word in words {
+		let automaton = spelling_automaton(&word, fuzzy_distance(&word));
+		let mut candidates: HashMap<String, (u8, u32)> = HashMap::new();
+		for inverted_index in &inverted_indices {
+			let mut terms = inverted_index.terms().search(&automaton).into_stream().ok()?;
+			while terms.advance() {
+				let Distance::Exact(distance) = automaton.0.eval(terms.key()) else {
+					continue;
+				};
+				let candidate = candidates
+					.entry(String::from_utf8_lossy(terms.key()).into_owned())
+					.or_insert((distance, 0));
+				candidate.1 += terms.value().doc_freq;
+			}
+		}
+		let best = candidates
+			.into_iter()
+			.min_by(|(w1, (d1, f1)), (w2, (d2, f2))| d1.cmp(d2).then(f2.cmp(f1)).then(w1.cmp(w2)));
+		match best {
+			Some((candidate, (distance, _))) if distance > 0 => {
+				changed = true;
+				suggestion.push(candidate);
+			}
+			_ => suggestion.push(word),
+		}
+	}
+
+	changed.then(|| suggestion.join(" "))
+}
+
+/// Finds the packages with the most similar readme, description and tags to the given package,
+/// only packages with a version compatible with the given [QueryVersions].
+pub fn related(
+	searcher: &Searcher,
+	schema: &PackageSchema,
+	id: &str,
+	versions: &QueryVersions,
+	limit: usize,
+) -> tantivy::Result<Vec<SearchHit>> {
+	let id_term = Term::from_field_text(schema.id, id);
+	let package = searcher.search(
+		&TermQuery::new(id_term.clone(), IndexRecordOption::Basic),
+		&TopDocs::with_limit(1),
+	)?;
+	let Some((_, address)) = package.into_iter().next() else {
+		return Ok(Vec::new());
+	};
+	let doc: TantivyDocument = searcher.doc(address)?;
+	let fields = [schema.readme, schema.short_description, schema.tags]
+		.into_iter()
+		.map(|field| (field, doc.get_all(field).cloned().collect()))
+		.collect();
+
+	// the index is small, so even words of only two packages are significant
+	let similar = MoreLikeThisQuery::builder()
+		.with_min_doc_frequency(1)
+		.with_min_term_frequency(1)
+		.with_min_word_length(3)
+		.with_max_query_terms(25)
+		.with_document_fields(fields);
+	let query = BooleanQuery::new(vec![
+		(Occur::Must, Box::new(similar) as Box<dyn Query>),
+		(
+			Occur::Must,
+			Box::new(TermQuery::new(
+				Term::from_field_text(schema.kind, KIND_PACKAGE),
+				IndexRecordOption::Basic,
+			)),
+		),
+		(
+			Occur::MustNot,
+			Box::new(TermQuery::new(id_term, IndexRecordOption::Basic)),
+		),
+	]);
+
+	let top_docs = searcher.search(&query, &version_filter(versions, TopDocs::with_limit(limit)))?;
+	let mut hits = Vec::new();
+	for (score, address) in top_docs {
+		let doc: TantivyDocument = searcher.doc(address)?;
+		let package_versions = read_package_versions(&doc, schema);
+		hits.push(SearchHit {
+			id: doc
+				.get_first(schema.id)
+				.and_then(|v| v.as_str())
+				.unwrap_or_default()
+				.to_string(),
+			score: Some(score),
+			version: latest_compatible_version(versions, &package_versions).cloned(),
+			versions: package_versions,
+			explanation: None,
+			highlight: None,
+			eeproms: Vec::new(),
+		});
+	}
+	Ok(hits)
+}
+
+/// A completion of a search prefix.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Completion {
+	/// One of [crate::index::COMPLETION_PACKAGE], [crate::index::COMPLETION_TAG] or [crate::index::COMPLETION_AUTHOR].
+	pub kind: String,
+	/// The package id, tag or author.
+	pub value: String,
+	pub label: String,
+	/// The number of packages with this completion.
+	pub count: u32,
+}
+
+/// Returns the package names, tags and authors with a word starting with the given prefix, most common first.
+pub fn complete(
+	searcher: &Searcher,
+	schema: &PackageSchema,
+	prefix: &str,
+	limit: usize,
+) -> tantivy::Result<Vec<Completion>> {
+	let prefix = prefix.trim().to_lowercase();
+	if prefix.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let mut completions: BTreeMap<(String, String), Completion> = BTreeMap::new();
+	for segment in searcher.segment_readers() {
+		let inverted_index = segment.inverted_index(schema.completion)?;
+		let mut terms = inverted_index.terms().range().ge(prefix.as_bytes()).into_stream()?;
+		while terms.advance() && terms.key().starts_with(prefix.as_bytes()) {
+			let term = String::from_utf8_lossy(terms.key());
+			let mut parts = term.split('\0').skip(1);
+			let (Some(kind), Some(value), Some(label)) = (parts.next(), parts.next(), parts.next())
+			else {
+				continue;
+			};
+			completions
+				.entry((kind.to_string(), value.to_string()))
+				.or_insert_with(|| Completion {
+					kind: kind.to_string(),
+					value: value.to_string(),
+					label: label.to_string(),
+					count: 0,
+				})
+				.count += terms.value().doc_freq;
+		}
+	}
+
+	let mut completions: Vec<_> = completions.into_values().collect();
+	completions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.label.cmp(&b.label)));
+	completions.truncate(limit);
+	Ok(completions)
+}
+
+/// The sort key of a document, results are ordered by descending keys and ties by ascending [DocAddress].
+type SortKey = Box<dyn Fn(DocId, Score) -> f64>;
+
+/// Returns the sort key of the documents of the segment.
+fn sort_key(sort: Sort, segment: &SegmentReader) -> SortKey {
+	let fast_fields = segment.fast_fields();
+	match sort {
+		Sort::Relevance => Box::new(|_, score| score as f64),
+		Sort::Name => {
+			let column = fast_fields.u64("name_rank").ok();
+			Box::new(move |doc, _| {
+				-(column.as_ref().and_then(|c| c.first(doc)).unwrap_or(u64::MAX) as f64)
+			})
+		}
+		Sort::Newest | Sort::Updated => {
+			let field = if sort == Sort::Newest { "published" } else { "updated" };
+			let column = fast_fields.date(field).ok();
+			Box::new(move |doc, _| {
+				column
+					.as_ref()
+					.and_then(|c| c.first(doc))
+					.map_or(f64::MIN, |date| date.into_timestamp_secs() as f64)
+			})
+		}
+		Sort::Popular => {
+			let column = fast_fields.u64("dependents").ok();
+			Box::new(move |doc, _| column.as_ref().and_then(|c| c.first(doc)).unwrap_or(0) as f64)
+		}
+	}
+}
+
+type Page = (Vec<(f64, DocAddress)>, usize, FacetCounts);
+
+/// Collects a page of up to `limit` hits after the cursor, or after `offset` hits if there is no cursor,
+/// together with the total count and the facet counts of the filtered results.
+fn collect_page(
+	searcher: &Searcher,
+	query: &dyn Query,
+	request: &SearchRequest,
+	sort: Sort,
+	cursor: Option<Cursor>,
+	limit: usize,
+) -> tantivy::Result<Page> {
+	let mut facet_collector = FacetCollector::for_field("facets");
+	for root in [FACET_TAGS, FACET_AUTHORS, FACET_MODS, FACET_FIN] {
+		facet_collector.add_facet(Facet::from_path([root]));
+	}
+
+	let segment_ords: HashMap<SegmentId, u32> = searcher
+		.segment_readers()
+		.iter()
+		.enumerate()
+		.map(|(ord, segment)| (segment.segment_id(), ord as u32))
+		.collect();
+	let offset = if cursor.is_some() { 0 } else { request.offset };
+	// hits up to the cursor get no key, so they rank last and are left out
+	let top_docs = TopDocs::with_limit(limit)
+		.and_offset(offset)
+		.tweak_score(move |segment: &SegmentReader| {
+			let segment_ord = segment_ords.get(&segment.segment_id()).copied().unwrap_or_default();
+			let sort_key = sort_key(sort, segment);
+			move |doc: DocId, score: Score| {
+				let key = sort_key(doc, score);
+				cursor
+					.is_none_or(|cursor| cursor.precedes(key, DocAddress::new(segment_ord, doc)))
+					.then_some(key)
+			}
+		});
+
+	let (docs, total, counts) = searcher.search(
+		query,
+		&version_filter(&request.versions, (top_docs, Count, facet_collector)),
+	)?;
+	let docs = docs
+		.into_iter()
+		.filter_map(|(key, address)| Some((key?, address)))
+		.collect();
+	Ok((docs, total, counts))
+}
+
+/// Searches the packages matching the request, filtered, sorted and paged as requested.
+pub fn search(
+	searcher: &Searcher,
+	schema: &PackageSchema,
+	request: &SearchRequest,
+) -> Result<SearchResult, SearchError> {
+	let sort = match request.sort {
+		Sort::Relevance if request.query.trim().is_empty() => Sort::Name,
+		sort => sort,
+	};
+	// a cursor of another order would skip arbitrary hits
+	let cursor = request.cursor.filter(|cursor| cursor.sort == sort);
+	if cursor.is_none() && request.offset > MAX_OFFSET {
+		return Err(SearchError::Offset(request.offset));
+	}
+	let query_errors = match request.query.trim() {
+		"" => None,
+		query => check_query(searcher, schema, query),
+	};
+	if let Some(invalid) = query_errors.clone().filter(|_| !request.lenient) {
+		return Err(invalid.into());
+	}
+	// excluding EEPROMs would only join the packages of all other EEPROMs
+	let query = request.query.trim();
+	let eeproms = if query.is_empty() || only_excludes(&query_grammar::parse_query_lenient(query).0) {
+		Vec::new()
+	} else {
+		search_eeproms(searcher, schema, request)?
+	};
+	let mut eeprom_packages: Vec<String> = eeproms.iter().map(|e| e.package.clone()).collect();
+	eeprom_packages.sort();
+	eeprom_packages.dedup();
+	let query = build_query(searcher, schema, request, request.fuzzy, &eeprom_packages);
+
+	let limit = request.limit.clamp(1, MAX_PAGE_SIZE);
+	let query = query.as_ref();
+	// one more hit than requested tells if there is another page
+	let (mut top_docs, total, counts) = collect_page(searcher, query, request, sort, cursor, limit + 1)?;
+	let has_more = top_docs.len() > limit;
+	top_docs.truncate(limit);
+	let next_cursor = top_docs
+		.last()
+		.filter(|_| has_more)
+		.map(|(key, address)| Cursor {
+			sort,
+			key: *key,
+			address: *address,
+		});
+
+	// the description is preferred, it is shorter and shown anyways
+	let highlighters = if request.query.trim().is_empty() {
+		Vec::new()
+	} else {
+		[("short_description", schema.short_description), ("readme", schema.readme)]
+			.into_iter()
+			.map(|(name, field)| {
+				let mut generator = SnippetGenerator::create(searcher, query, field)?;
+				generator.set_max_num_chars(MAX_HIGHLIGHT_CHARS);
+				Ok((name, generator))
+			})
+			.collect::<tantivy::Result<Vec<_>>>()?
+	};
+
+	let mut hits = Vec::new();
+	for (key, address) in top_docs {
+		let doc: TantivyDocument = searcher.doc(address)?;
+		let highlight = highlight(&highlighters, &doc);
+		let versions = read_package_versions(&doc, schema);
+		let explanation = if request.explain {
+			Some(query.explain(searcher, address)?.to_pretty_json())
+		} else {
+			None
+		};
+		let id = doc
+			.get_first(schema.id)
+			.and_then(|v| v.as_str())
+			.unwrap_or_default()
+			.to_string();
+		let mut package_eeproms: Vec<EepromHit> = Vec::new();
+		for eeprom in eeproms.iter().filter(|e| e.package == id) {
+			match package_eeproms.iter_mut().find(|e| e.file == eeprom.file) {
+				Some(newest) if newest.version < eeprom.version => *newest = eeprom.clone(),
+				Some(_) => {}
+				None => package_eeproms.push(eeprom.clone()),
+			}
+		}
+		hits.push(SearchHit {
+			id,
+			score: (sort == Sort::Relevance).then_some(key as f32),
+			version: latest_compatible_version(&request.versions, &versions).cloned(),
+			versions,
+			explanation,
+			highlight,
+			eeproms: package_eeproms,
+		});
+	}
+
+	let facets = SearchFacets {
+		tags: facet_counts(&counts, FACET_TAGS),
+		authors: facet_counts(&counts, FACET_AUTHORS),
+		mods: facet_counts(&counts, FACET_MODS),
+		fin_versions: facet_counts(&counts, FACET_FIN),
+	};
+
+	// only suggest a spelling if nothing matched the query as written
+	let suggestion = if request.query.trim().is_empty() {
+		None
+	} else {
+		let exact = build_query(searcher, schema, request, false, &eeprom_packages);
+		match searcher.search(&exact, &version_filter(&request.versions, Count))? {
+			0 => suggest_spelling(searcher, schema, &request.query),
+			_ => None,
+		}
+	};
+
+	Ok(SearchResult {
+		hits,
+		total,
+		facets,
+		suggestion,
+		query_errors,
+		has_more,
+		next_cursor,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures;
+	use crate::index::{completion_terms, COMPLETION_AUTHOR, COMPLETION_PACKAGE, COMPLETION_TAG};
+
+	fn fixture() -> (Searcher, PackageSchema) {
+		fixtures::index(fixtures::packages())
+	}
+
+	fn ids(result: &SearchResult) -> Vec<&str> {
+		let mut ids: Vec<_> = result.hits.iter().map(|h| h.id.as_str()).collect();
+		ids.sort();
+		ids
+	}
+
+	fn fin(version: &str) -> QueryVersions {
+		QueryVersions {
+			fin_version: Some(semver::Version::parse(version).unwrap()),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn empty_query_matches_all() {
+		let (searcher, schema) = fixture();
+		let result = search(&searcher, &schema, &SearchRequest::default()).unwrap();
+		assert_eq!(ids(&result), ["Clock", "NetBoot", "PowerMonitor"]);
+		assert_eq!(result.total, 3);
+	}
+
+	#[test]
+	fn filters_by_compatibility_and_matches_version() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			versions: fin("0.3.19"),
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		let netboot = result.hits.iter().find(|h| h.id == "NetBoot").unwrap();
+		assert_eq!(netboot.version, Some(semver::Version::new(0, 1, 0)));
+		assert_eq!(netboot.versions.len(), 2);
+
+		let request = SearchRequest {
+			versions: fin("0.2.0"),
+			..Default::default()
+		};
+		assert_eq!(search(&searcher, &schema, &request).unwrap().total, 0);
+	}
+
+	#[test]
+	fn checks_mods() {
+		let (searcher, schema) = fixture();
+		let mut versions = fin("0.3.19");
+		versions.check_mods = true;
+		let request = SearchRequest {
+			versions: versions.clone(),
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["Clock", "NetBoot"]);
+
+		versions.mods.insert("SML".to_string(), Some(semver::Version::new(3, 7, 0)));
+		let request = SearchRequest {
+			versions,
+			..Default::default()
+		};
+		assert_eq!(search(&searcher, &schema, &request).unwrap().total, 3);
+	}
+
+	#[test]
+	fn pages_keep_total() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			limit: 2,
+			offset: 2,
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(result.hits.len(), 1);
+		assert_eq!(result.total, 3);
+		assert!(!result.has_more);
+		assert_eq!(result.next_cursor, None);
+
+		let request = SearchRequest {
+			limit: 1000,
+			..Default::default()
+		};
+		assert_eq!(search(&searcher, &schema, &request).unwrap().hits.len(), 3);
+
+		let request = SearchRequest {
+			offset: MAX_OFFSET + 1,
+			..Default::default()
+		};
+		assert!(matches!(
+			search(&searcher, &schema, &request),
+			Err(SearchError::Offset(_))
+		));
+	}
+
+	#[test]
+	fn cursors_continue_pages() {
+		let (searcher, schema) = fixture();
+		for (query, sort) in [("", Sort::Name), ("utility OR boot", Sort::Relevance), ("", Sort::Popular)] {
+			let request = SearchRequest {
+				query: query.to_string(),
+				sort,
+				limit: 100,
+				..Default::default()
+			};
+			let all: Vec<_> = search(&searcher, &schema, &request)
+				.unwrap()
+				.hits
+				.into_iter()
+				.map(|h| h.id)
+				.collect();
+
+			let mut paged = Vec::new();
+			let mut request = SearchRequest { limit: 1, ..request };
+			loop {
+				let result = search(&searcher, &schema, &request).unwrap();
+				assert_eq!(result.total, all.len());
+				paged.extend(result.hits.into_iter().map(|h| h.id));
+				let Some(cursor) = result.next_cursor else {
+					assert!(!result.has_more);
+					break;
+				};
+				assert!(result.has_more);
+				assert_eq!(cursor.to_string().parse::<Cursor>(), Ok(cursor));
+				request.cursor = Some(cursor);
+			}
+			assert_eq!(paged, all, "{sort}");
+		}
+
+		let cursor = Cursor {
+			sort: Sort::Newest,
+			key: f64::MIN,
+			address: DocAddress::new(0, 7),
+		};
+		assert_eq!(cursor.to_string(), "newest_ffefffffffffffff_0_7");
+		assert_eq!(cursor.to_string().parse::<Cursor>(), Ok(cursor));
+		assert!("newest_1.5_0_7".parse::<Cursor>().is_err());
+		assert!("name_1".parse::<Cursor>().is_err());
+	}
+
+	#[test]
+	fn query_and_provides() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			query: "tags:utility".to_string(),
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["Clock", "PowerMonitor"]);
+
+		let request = SearchRequest {
+			provides: Some("bootloader".to_string()),
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["NetBoot"]);
+	}
+
+	#[test]
+	fn invalid_query() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			query: "unknown_field:foo".to_string(),
+			..Default::default()
+		};
+		assert!(matches!(
+			search(&searcher, &schema, &request),
+			Err(SearchError::Query(_))
+		));
+
+		let request = SearchRequest {
+			query: "tags:\"utility".to_string(),
+			..Default::default()
+		};
+		let Err(SearchError::Query(invalid)) = search(&searcher, &schema, &request) else {
+			panic!("unclosed quote is invalid");
+		};
+		assert_eq!(invalid.errors[0].position, Some(13));
+		assert_eq!(invalid.suggestion.as_deref(), Some("tags:\"utility\""));
+	}
+
+	#[test]
+	fn suggests_query_fixes() {
+		let (searcher, schema) = fixture();
+		let fix = |query: &str| check_query(&searcher, &schema, query).and_then(|e| e.suggestion);
+		assert_eq!(fix("tagz:power").as_deref(), Some("tags:power"));
+		assert_eq!(fix("unknown_field:foo").as_deref(), Some("foo"));
+		assert_eq!(fix("(power OR clock").as_deref(), Some("(power OR clock)"));
+		assert_eq!(fix("power)").as_deref(), Some("power"));
+		assert_eq!(fix("power:").as_deref(), Some("power"));
+		for query in ["-", ":", ")"] {
+			assert_eq!(fix(query), None, "{query}");
+		}
+		assert_eq!(check_query(&searcher, &schema, "tags:power AND clock"), None);
+	}
+
+	#[test]
+	fn lenient_search_drops_broken_clauses() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			query: "tagz:boot clock".to_string(),
+			fuzzy: false,
+			lenient: true,
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(ids(&result), ["Clock"]);
+		let invalid = result.query_errors.unwrap();
+		assert_eq!(invalid.errors[0].position, Some(0));
+		assert_eq!(invalid.suggestion.as_deref(), Some("tags:boot clock"));
+
+		let request = SearchRequest {
+			query: "clock".to_string(),
+			lenient: true,
+			..Default::default()
+		};
+		assert_eq!(search(&searcher, &schema, &request).unwrap().query_errors, None);
+	}
+
+	#[test]
+	fn facets_count_matching_packages() {
+		let (searcher, schema) = fixture();
+		let result = search(&searcher, &schema, &SearchRequest::default()).unwrap();
+		assert_eq!(
+			result.facets.tags[0],
+			FacetCount {
+				value: "utility".to_string(),
+				facet: "/tags/utility".to_string(),
+				count: 2
+			}
+		);
+		assert_eq!(result.facets.tags.len(), 4);
+		assert_eq!(result.facets.authors[0].value, "Panakotta00");
+		assert_eq!(result.facets.mods[0].facet, "/mods/SML");
+		let fin_versions: Vec<_> = result
+			.facets
+			.fin_versions
+			.iter()
+			.map(|f| (f.value.as_str(), f.count))
+			.collect();
+		assert_eq!(fin_versions, [("0.3", 3), ("0.4", 1)]);
+
+		let request = SearchRequest {
+			versions: fin("0.3.0"),
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(
+			result.facets.tags,
+			[FacetCount {
+				value: "utility".to_string(),
+				facet: "/tags/utility".to_string(),
+				count: 1
+			}]
+		);
+	}
+
+	#[test]
+	fn filters_by_facets() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			facets: vec![Facet::from_text("/tags/utility").unwrap()],
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["Clock", "PowerMonitor"]);
+
+		let request = SearchRequest {
+			facets: vec![
+				Facet::from_text("/tags/utility").unwrap(),
+				Facet::from_path(["authors", "Panakotta00"]),
+			],
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(ids(&result), ["Clock"]);
+		assert_eq!(result.facets.tags.len(), 1);
+
+		let request = SearchRequest {
+			facets: vec![Facet::from_text("/fin/0.4").unwrap()],
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["NetBoot"]);
+	}
+
+	fn sorted(searcher: &Searcher, schema: &PackageSchema, sort: Sort) -> Vec<String> {
+		let request = SearchRequest {
+			sort,
+			..Default::default()
+		};
+		search(searcher, schema, &request)
+			.unwrap()
+			.hits
+			.into_iter()
+			.map(|h| h.id)
+			.collect()
+	}
+
+	#[test]
+	fn sort_orders() {
+		let (searcher, schema) = fixture();
+		assert_eq!(sorted(&searcher, &schema, Sort::Relevance), ["Clock", "NetBoot", "PowerMonitor"]);
+		assert_eq!(sorted(&searcher, &schema, Sort::Name), ["Clock", "NetBoot", "PowerMonitor"]);
+		assert_eq!(sorted(&searcher, &schema, Sort::Newest), ["Clock", "NetBoot", "PowerMonitor"]);
+		assert_eq!(sorted(&searcher, &schema, Sort::Updated), ["NetBoot", "Clock", "PowerMonitor"]);
+		assert_eq!(sorted(&searcher, &schema, Sort::Popular), ["NetBoot", "Clock", "PowerMonitor"]);
+
+		let request = SearchRequest {
+			query: "utility".to_string(),
+			sort: Sort::Name,
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(result.hits[0].id, "Clock");
+		assert_eq!(result.hits[0].score, None);
+	}
+
+	#[test]
+	fn fuzzy_matches_misspelled_words() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			query: "netbot".to_string(),
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(ids(&result), ["NetBoot"]);
+		assert_eq!(result.suggestion.as_deref(), Some("netboot"));
+
+		let request = SearchRequest {
+			fuzzy: false,
+			..request
+		};
+		assert_eq!(search(&searcher, &schema, &request).unwrap().total, 0);
+	}
+
+	#[test]
+	fn exact_matches_rank_first() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			query: "clock".to_string(),
+			..Default::default()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		assert_eq!(result.hits[0].id, "Clock");
+		assert_eq!(result.suggestion, None);
+	}
+
+	#[test]
+	fn spelling_suggestions() {
+		let (searcher, schema) = fixture();
+		assert_eq!(
+			suggest_spelling(&searcher, &schema, "utilty pakage").as_deref(),
+			Some("utility package")
+		);
+		assert_eq!(suggest_spelling(&searcher, &schema, "power"), None);
+		assert_eq!(suggest_spelling(&searcher, &schema, "tags:utilty"), None);
+		assert_eq!(edit_distance("netbot", "netboot"), 1);
+		assert_eq!(edit_distance("ntebot", "netbot"), 1);
+	}
+
+	#[test]
+	fn completes_prefixes_of_any_word() {
+		let (searcher, schema) = fixture();
+		let completions = complete(&searcher, &schema, "Pana", 10).unwrap();
+		assert_eq!(
+			completions,
+			[Completion {
+				kind: COMPLETION_AUTHOR.to_string(),
+				value: "Panakotta00".to_string(),
+				label: "Panakotta00".to_string(),
+				count: 2,
+			}]
+		);
+
+		let completions = complete(&searcher, &schema, "u", 10).unwrap();
+		assert_eq!(completions[0].kind, COMPLETION_TAG);
+		assert_eq!(completions[0].value, "utility");
+
+		assert!(complete(&searcher, &schema, "", 10).unwrap().is_empty());
+		assert!(complete(&searcher, &schema, "xyz", 10).unwrap().is_empty());
+	}
+
+	#[test]
+	fn completion_terms_start_at_every_word() {
+		let terms = completion_terms(COMPLETION_PACKAGE, "NetBoot", "Net-Boot");
+		assert_eq!(terms, ["net-boot\0package\0NetBoot\0Net-Boot", "boot\0package\0NetBoot\0Net-Boot"]);
+	}
+
+	fn exact(query: &str) -> SearchRequest {
+		SearchRequest {
+			query: query.to_string(),
+			fuzzy: false,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn finds_camel_case_names() {
+		let (searcher, schema) = fixture();
+		for query in ["power monitor", "monitor", "powermonitor", "PowerMonitor", "\"power monitor\""] {
+			assert_eq!(ids(&search(&searcher, &schema, &exact(query)).unwrap()), ["PowerMonitor"], "{query}");
+		}
+		assert_eq!(search(&searcher, &schema, &exact("\"monitor power\"")).unwrap().total, 0);
+	}
+
+	#[test]
+	fn stems_descriptions() {
+		let (searcher, schema) = fixture();
+		assert_eq!(search(&searcher, &schema, &exact("packages")).unwrap().total, 3);
+		assert_eq!(search(&searcher, &schema, &exact("name:packages")).unwrap().total, 0);
+	}
+
+	#[test]
+	fn matches_prefixes() {
+		let (searcher, schema) = fixture();
+		let request = SearchRequest {
+			query: "util".to_string(),
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["Clock", "PowerMonitor"]);
+		assert_eq!(search(&searcher, &schema, &exact("util")).unwrap().total, 0);
+
+		let request = SearchRequest {
+			query: "pow".to_string(),
+			..Default::default()
+		};
+		assert_eq!(ids(&search(&searcher, &schema, &request).unwrap()), ["PowerMonitor"]);
+	}
+
+	#[test]
+	fn field_boosts() {
+		let (searcher, schema) = fixture();
+		// both ids match, so a higher id boost raises the score
+		let request = SearchRequest {
+			query: "netboot OR clock".to_string(),
+			fuzzy: false,
+			..Default::default()
+		};
+		let mut boosts = FieldBoosts::default();
+		boosts.set("id", 10.0).unwrap();
+		boosts.set("name", 0.0).unwrap();
+		let boosted = SearchRequest {
+			boosts,
+			..request.clone()
+		};
+		let result = search(&searcher, &schema, &request).unwrap();
+		let boosted = search(&searcher, &schema, &boosted).unwrap();
+		assert!(boosted.hits[0].score.unwrap() > result.hits[0].score.unwrap());
+
+		assert!(FieldBoosts::default().set("unknown", 1.0).is_err());
+	}
+
+	#[test]
+	fn highlights_matches() {
+		let (searcher, schema) = fixture();
+		let result = search(&searcher, &schema, &exact("packages")).unwrap();
+		let highlight = result.hits[0].highlight.as_ref().unwrap();
+		assert_eq!(highlight.field, "short_description");
+		assert!(highlight.html.contains("<b>package</b>"), "{}", highlight.html);
+
+		let result = search(&searcher, &schema, &exact("readme:install")).unwrap();
+		assert_eq!(ids(&result), ["Clock"]);
+		let highlight = result.hits[0].highlight.as_ref().unwrap();
+		assert_eq!(highlight.field, "readme");
+		assert_eq!(highlight.html, "Run the &lt;Clock&gt; <b>installer</b>");
+
+		let result = search(&searcher, &schema, &SearchRequest::default()).unwrap();
+		assert!(result.hits.iter().all(|h| h.highlight.is_none()));
+	}
+
+	#[test]
+	fn related_packages() {
+		let (searcher, schema) = fixture();
+		let related_ids = |id: &str, versions: &QueryVersions| -> Vec<String> {
+			related(&searcher, &schema, id, versions, 10)
+				.unwrap()
+				.into_iter()
+				.map(|h| h.id)
+				.collect()
+		};
+		// Clock and PowerMonitor share the utility tag
+		assert_eq!(related_ids("Clock", &QueryVersions::default())[0], "PowerMonitor");
+		assert!(!related_ids("Clock", &QueryVersions::default()).contains(&"Clock".to_string()));
+		assert!(related_ids("Clock", &fin("0.3.0")).is_empty());
+		assert!(related_ids("Unknown", &QueryVersions::default()).is_empty());
+	}
+
+	#[test]
+	fn searches_eeprom_code() {
+		let (searcher, schema) = fixture();
+		for query in ["findComponent", "find component", "GPUT1"] {
+			let result = search(&searcher, &schema, &exact(query)).unwrap();
+			assert_eq!(ids(&result), ["PowerMonitor"], "{query}");
+			let eeprom = &result.hits[0].eeproms[0];
+			assert_eq!(eeprom.file, "Monitor.lua");
+			assert_eq!(eeprom.version, semver::Version::new(0, 0, 1));
+			assert_eq!(eeprom.highlight.as_ref().unwrap().field, "code");
+		}
+		// the EEPROMs are no packages of their own
+		assert_eq!(search(&searcher, &schema, &SearchRequest::default()).unwrap().total, 3);
+		assert!(search(&searcher, &schema, &exact("clock")).unwrap().hits[0].eeproms.is_empty());
+	}
+
+	#[test]
+	fn searches_requirements() {
+		let (searcher, schema) = fixture();
+		let result = search(&searcher, &schema, &exact("getPCIDevices")).unwrap();
+		let netboot = result.hits.iter().find(|h| h.id == "NetBoot").unwrap();
+		assert_eq!(
+			netboot.eeproms[0].requirements,
+			["NetworkCard", "computer.getPCIDevices", "event.listen"]
+		);
+		// only NetBoot 0.1.0 uses a GPU, the requirements of the latest version count
+		assert_eq!(ids(&search(&searcher, &schema, &exact("requires:gpu")).unwrap()), ["PowerMonitor"]);
+		assert_eq!(ids(&search(&searcher, &schema, &exact("requires:\"network card\"")).unwrap()), ["NetBoot"]);
+		assert_eq!(ids(&search(&searcher, &schema, &exact("-requires:gpu")).unwrap()), ["Clock", "NetBoot"]);
+		assert_eq!(ids(&search(&searcher, &schema, &exact("utility -requires:gpu")).unwrap()), ["Clock"]);
+	}
+}
@@ -0,0 +1,219 @@
+use crate::compat::QueryVersions;
+use crate::game::parse_game_version_req;
+use crate::util::{parse_version_lenient, parse_version_req_lenient, version_req_lower_bound};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The mod reference of FicsIt-Networks.
+pub const FIN_MOD_ID: &str = "FicsItNetworks";
+/// The pseudo mod mods use to declare which game builds they support.
+const GAME_MOD_ID: &str = "FactoryGame";
+
+/// Supports the lockfiles and profile files of the Satisfactory Mod Manager and ficsit-cli.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SmmFile {
+	Profiles {
+		profiles: HashMap<String, ModList>,
+		#[serde(default)]
+		selected_profile: Option<String>,
+	},
+	ModList(ModList),
+	LegacyLockfile(HashMap<String, ModEntry>),
+}
+
+#[derive(Deserialize)]
+struct ModList {
+	mods: HashMap<String, ModEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModEntry {
+	/// The installed version in lockfiles, a version requirement in profiles.
+	version: String,
+	#[serde(default = "default_enabled")]
+	enabled: bool,
+	#[serde(default)]
+	dependencies: HashMap<String, String>,
+}
+
+fn default_enabled() -> bool {
+	true
+}
+
+/// The mods and versions a user has installed according to their mod manager.
+#[derive(Clone, Debug, Default)]
+pub struct SmmImport {
+	pub fin_version: Option<semver::Version>,
+	pub game_version: Option<semver::Version>,
+	pub mods: HashMap<String, Option<semver::Version>>,
+}
+
+impl SmmImport {
+	pub fn query_versions(&self) -> QueryVersions {
+		QueryVersions {
+			fin_version: self.fin_version.clone(),
+			game_version: self.game_version.clone(),
+			check_mods: true,
+			mods: self.mods.clone(),
+			..Default::default()
+		}
+	}
+}
+
+fn mod_version(version: &str) -> Option<semver::Version> {
+	match parse_version_lenient(version) {
+		Ok(version) => Some(version.value),
+		Err(_) => version_req_lower_bound(&parse_version_req_lenient(version).ok()?.value),
+	}
+}
+
+/// Parses a mod manager lockfile or profile file.
+/// If the file contains multiple profiles, the given one is used, otherwise the selected one.
+pub fn parse_smm(json: &str, profile: Option<&str>) -> Result<SmmImport, String> {
+	let file: SmmFile = serde_json::from_str(json)
+		.map_err(|e| format!("not a Satisfactory Mod Manager lockfile or profile: {e}"))?;
+	let mods = match file {
+		SmmFile::Profiles {
+			mut profiles,
+			selected_profile,
+		} => {
+			let name = match profile.map(str::to_string).or(selected_profile) {
+				Some(name) => name,
+				None if profiles.len() == 1 => profiles.keys().next().cloned().unwrap_or_default(),
+				None => {
+					let mut names: Vec<_> = profiles.keys().cloned().collect();
+					names.sort();
+					return Err(format!("multiple profiles found, choose one of: {}", names.join(", ")));
+				}
+			};
+			profiles
+				.remove(&name)
+				.ok_or_else(|| format!("profile '{name}' not found"))?
+				.mods
+		}
+		SmmFile::ModList(list) => list.mods,
+		SmmFile::LegacyLockfile(mods) => mods,
+	};
+
+	let game_version = mods
+		.values()
+		.filter(|m| m.enabled)
+		.filter_map(|m| m.dependencies.get(GAME_MOD_ID))
+		.filter_map(|req| version_req_lower_bound(&parse_game_version_req(req).ok()?))
+		.max();
+
+	let mods: HashMap<_, _> = mods
+		.into_iter()
+		.filter(|(id, m)| m.enabled && id != GAME_MOD_ID)
+		.map(|(id, m)| (id, mod_version(&m.version)))
+		.collect();
+
+	Ok(SmmImport {
+		fin_version: mods.get(FIN_MOD_ID).cloned().flatten(),
+		game_version,
+		mods,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PROFILES: &str = r#"{
+		"profiles": {
+			"Default": {
+				"mods": {
+					"FicsItNetworks": { "version": ">=0.3.19", "enabled": true },
+					"SML": { "version": "^3.8.0", "enabled": true }
+				}
+			},
+			"Vanilla": { "mods": {} }
+		},
+		"selected_profile": "Default",
+		"version": 0
+	}"#;
+
+	const MOD_LIST: &str = r#"{
+		"mods": {
+			"FicsItNetworks": {
+				"version": "0.3.23",
+				"hash": "0000",
+				"link": "https://example.com/FicsItNetworks.zip",
+				"dependencies": { "SML": "^3.8.0", "FactoryGame": ">=365306" }
+			},
+			"SML": {
+				"version": "3.8.0",
+				"dependencies": { "FactoryGame": ">=264901" }
+			},
+			"PowerSuit": { "version": "1.2", "enabled": false }
+		},
+		"version": 1
+	}"#;
+
+	const LEGACY_LOCKFILE: &str = r#"{
+		"FicsItNetworks": { "version": "0.3.19", "dependencies": { "SML": "^3.6.0" } },
+		"SML": { "version": "3.6.1", "dependencies": { "FactoryGame": ">=211839" } }
+	}"#;
+
+	fn mods(import: &SmmImport) -> Vec<(String, Option<String>)> {
+		let mut mods: Vec<_> = import
+			.mods
+			.iter()
+			.map(|(id, version)| (id.clone(), version.as_ref().map(|v| v.to_string())))
+			.collect();
+		mods.sort();
+		mods
+	}
+
+	fn installed(mods: &[(&str, &str)]) -> Vec<(String, Option<String>)> {
+		mods.iter()
+			.map(|(id, version)| (id.to_string(), Some(version.to_string())))
+			.collect()
+	}
+
+	#[test]
+	fn reads_profiles() {
+		let import = parse_smm(PROFILES, None).unwrap();
+		assert_eq!(mods(&import), installed(&[("FicsItNetworks", "0.3.19"), ("SML", "3.8.0")]));
+		assert_eq!(import.fin_version, Some(semver::Version::new(0, 3, 19)));
+		assert_eq!(import.game_version, None);
+
+		let import = parse_smm(PROFILES, Some("Vanilla")).unwrap();
+		assert!(import.mods.is_empty());
+		assert_eq!(import.fin_version, None);
+
+		assert_eq!(parse_smm(PROFILES, Some("Missing")).unwrap_err(), "profile 'Missing' not found");
+		let unselected = PROFILES.replace("\"selected_profile\": \"Default\",", "");
+		assert_eq!(
+			parse_smm(&unselected, None).unwrap_err(),
+			"multiple profiles found, choose one of: Default, Vanilla"
+		);
+	}
+
+	#[test]
+	fn reads_mod_lists() {
+		let import = parse_smm(MOD_LIST, None).unwrap();
+		assert_eq!(mods(&import), installed(&[("FicsItNetworks", "0.3.23"), ("SML", "3.8.0")]));
+		assert_eq!(import.fin_version, Some(semver::Version::new(0, 3, 23)));
+		assert_eq!(import.game_version, Some(semver::Version::new(1, 0, 0)));
+
+		let query = import.query_versions();
+		assert!(query.check_mods);
+		assert_eq!(query.game_version, Some(semver::Version::new(1, 0, 0)));
+	}
+
+	#[test]
+	fn reads_legacy_lockfiles() {
+		let import = parse_smm(LEGACY_LOCKFILE, None).unwrap();
+		assert_eq!(mods(&import), installed(&[("FicsItNetworks", "0.3.19"), ("SML", "3.6.1")]));
+		assert_eq!(import.game_version, Some(semver::Version::new(0, 7, 0)));
+	}
+
+	#[test]
+	fn rejects_other_files() {
+		assert!(parse_smm("{ \"profiles\": 3 }", None).is_err());
+		assert!(parse_smm("[]", None).is_err());
+		assert!(parse_smm("not json", None).is_err());
+	}
+}
@@ -0,0 +1,211 @@
+use tantivy::tokenizer::{
+	Language, LowerCaser, RemoveLongFilter, Stemmer, TextAnalyzer, Token, TokenStream, Tokenizer,
+};
+use tantivy::Index;
+
+/// The analyzer of ids and names, see [IdentifierTokenizer].
+pub const IDENTIFIER_TOKENIZER: &str = "identifier";
+/// The analyzer of descriptions and readmes, like [IDENTIFIER_TOKENIZER] but with English stemming.
+pub const ENGLISH_TOKENIZER: &str = "identifier_en";
+
+/// Splits text at whitespace and punctuation like the default tokenizer, so snake_case and kebab-case words are split,
+/// and CamelCase words additionally at their case changes.
+/// A CamelCase word is also kept as a whole at the position of its first part,
+/// so `PowerMonitor` matches `power monitor` and `powermonitor`.
+#[derive(Clone, Default)]
+pub struct IdentifierTokenizer {
+	token: Token,
+}
+
+pub struct IdentifierTokenStream<'a> {
+	text: &'a str,
+	offset: usize,
+	/// The end of the current CamelCase word, while its parts are emitted.
+	word_end: usize,
+	token: &'a mut Token,
+}
+
+impl Tokenizer for IdentifierTokenizer {
+	type TokenStream<'a> = IdentifierTokenStream<'a>;
+
+	fn token_stream<'a>(&'a mut self, text: &'a str) -> IdentifierTokenStream<'a> {
+		self.token.reset();
+		IdentifierTokenStream {
+			text,
+			offset: 0,
+			word_end: 0,
+			token: &mut self.token,
+		}
+	}
+}
+
+/// Returns the end of the first CamelCase part of the word,
+/// a part starts at an upper case letter after a lower case one, or before the last letter of an upper case run.
+fn part_end(word: &str) -> usize {
+	let mut chars = word.char_indices().peekable();
+	let mut prev: Option<char> = None;
+	while let Some((i, c)) = chars.next() {
+		if !c.is_alphanumeric() {
+			return i;
+		}
+		if let Some(prev) = prev {
+			let next_lower = chars.peek().is_some_and(|(_, next)| next.is_lowercase());
+			if c.is_uppercase() && (prev.is_lowercase() || (prev.is_uppercase() && next_lower)) {
+				return i;
+			}
+		}
+		prev = Some(c);
+	}
+	word.len()
+}
+
+impl<'a> IdentifierTokenStream<'a> {
+	fn emit(&mut self, from: usize, to: usize) {
+		self.token.offset_from = from;
+		self.token.offset_to = to;
+		self.token.text.push_str(&self.text[from..to]);
+		self.offset = to;
+	}
+}
+
+impl<'a> TokenStream for IdentifierTokenStream<'a> {
+	fn advance(&mut self) -> bool {
+		self.token.text.clear();
+
+		// the first part of a CamelCase word shares the position of the whole word
+		if self.offset < self.word_end && self.offset == self.token.offset_from {
+			let to = self.offset + part_end(&self.text[self.offset..]);
+			self.emit(self.offset, to);
+			return true;
+		}
+		self.token.position = self.token.position.wrapping_add(1);
+
+		let Some(from) = self.text[self.offset..]
+			.char_indices()
+			.find(|(_, c)| c.is_alphanumeric())
+			.map(|(i, _)| self.offset + i)
+		else {
+			self.offset = self.text.len();
+			return false;
+		};
+		if from >= self.word_end {
+			let word_end = self.text[from..]
+				.find(|c: char| !c.is_alphanumeric())
+				.map_or(self.text.len(), |i| from + i);
+			let to = from + part_end(&self.text[from..]);
+			if to < word_end {
+				self.word_end = word_end;
+				self.emit(from, word_end);
+				self.offset = from;
+				return true;
+			}
+		}
+		let to = from + part_end(&self.text[from..]);
+		self.emit(from, to);
+		true
+	}
+
+	fn token(&self) -> &Token {
+		self.token
+	}
+
+	fn token_mut(&mut self) -> &mut Token {
+		self.token
+	}
+}
+
+/// Registers the analyzers used by the schema of [crate::index::build_schema],
+/// has to be done for every opened index before indexing or searching.
+pub fn register_tokenizers(index: &Index) {
+	index.tokenizers().register(
+		IDENTIFIER_TOKENIZER,
+		TextAnalyzer::builder(IdentifierTokenizer::default())
+			.filter(RemoveLongFilter::limit(40))
+			.filter(LowerCaser)
+			.build(),
+	);
+	index.tokenizers().register(
+		ENGLISH_TOKENIZER,
+		TextAnalyzer::builder(IdentifierTokenizer::default())
+			.filter(RemoveLongFilter::limit(40))
+			.filter(LowerCaser)
+			.filter(Stemmer::new(Language::English))
+			.build(),
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn tokens(text: &str) -> Vec<(String, usize, usize, usize)> {
+		let mut tokenizer = IdentifierTokenizer::default();
+		let mut stream = tokenizer.token_stream(text);
+		let mut tokens = Vec::new();
+		while stream.advance() {
+			let token = stream.token();
+			tokens.push((token.text.clone(), token.position, token.offset_from, token.offset_to));
+		}
+		tokens
+	}
+
+	fn token(text: &str, position: usize, from: usize, to: usize) -> (String, usize, usize, usize) {
+		(text.to_string(), position, from, to)
+	}
+
+	#[test]
+	fn splits_camel_case() {
+		assert_eq!(
+			tokens("PowerMonitor"),
+			[token("PowerMonitor", 0, 0, 12), token("Power", 0, 0, 5), token("Monitor", 1, 5, 12)]
+		);
+		assert_eq!(
+			tokens("HTTPServer"),
+			[token("HTTPServer", 0, 0, 10), token("HTTP", 0, 0, 4), token("Server", 1, 4, 10)]
+		);
+		assert_eq!(
+			tokens("getPCIDevices"),
+			[
+				token("getPCIDevices", 0, 0, 13),
+				token("get", 0, 0, 3),
+				token("PCI", 1, 3, 6),
+				token("Devices", 2, 6, 13),
+			]
+		);
+	}
+
+	#[test]
+	fn keeps_upper_case_words_with_digits() {
+		assert_eq!(tokens("GPUT2"), [token("GPUT2", 0, 0, 5)]);
+		assert_eq!(
+			tokens("GPUT2 PowerMonitor"),
+			[
+				token("GPUT2", 0, 0, 5),
+				token("PowerMonitor", 1, 6, 18),
+				token("Power", 1, 6, 11),
+				token("Monitor", 2, 11, 18),
+			]
+		);
+	}
+
+	#[test]
+	fn splits_at_punctuation() {
+		assert_eq!(tokens("snake_case"), [token("snake", 0, 0, 5), token("case", 1, 6, 10)]);
+		assert_eq!(tokens("net-boot, v2"), [token("net", 0, 0, 3), token("boot", 1, 4, 8), token("v2", 2, 10, 12)]);
+		assert_eq!(tokens(" _ "), []);
+	}
+
+	#[test]
+	fn handles_non_ascii() {
+		assert_eq!(
+			tokens("ÜberWärme größe"),
+			[
+				token("ÜberWärme", 0, 0, 11),
+				token("Über", 0, 0, 5),
+				token("Wärme", 1, 5, 11),
+				token("größe", 2, 12, 19),
+			]
+		);
+		assert_eq!(tokens("日本語 テキスト"), [token("日本語", 0, 0, 9), token("テキスト", 1, 10, 22)]);
+	}
+}
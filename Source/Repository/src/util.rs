@@ -57,7 +57,9 @@ impl<'d> Visitor<'d> for SerializeSemverReqVisitor {
 	}
 
 	fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Self::Value, E> {
-		semver::VersionReq::parse(s).map_err(serde::de::Error::custom)
+		parse_version_req_lenient(s)
+			.map(|req| req.value)
+			.map_err(serde::de::Error::custom)
 	}
 }
 
@@ -71,3 +73,151 @@ pub fn deserialize_semver_req_opt<'d, D: Deserializer<'d>>(
 ) -> Result<Option<semver::VersionReq>, D::Error> {
 	Option::<DeserializeSemverReq>::deserialize(d).map(|o| o.map(|v| v.0))
 }
+
+/// A leniently parsed value and the warning to show if the input was not strictly valid.
+pub struct Lenient<T> {
+	pub value: T,
+	pub warning: Option<String>,
+}
+
+const VERSION_OPERATORS: &str = "<>=~^";
+
+fn normalize_version(s: &str) -> String {
+	let s = s.trim();
+	let s = s.strip_prefix(['v', 'V']).unwrap_or(s);
+	let (core, rest) = s.split_at(s.find(['-', '+']).unwrap_or(s.len()));
+	let mut parts: Vec<&str> = core.split('.').collect();
+	while parts.len() < 3 {
+		parts.push("0");
+	}
+	format!("{}{rest}", parts.join("."))
+}
+
+fn normalize_version_req(s: &str) -> String {
+	let mut comparators = Vec::new();
+	let mut pending_operator = "";
+	for token in s.split([',', ' ', '\t']).filter(|t| !t.is_empty()) {
+		let (operator, version) =
+			token.split_at(token.find(|c| !VERSION_OPERATORS.contains(c)).unwrap_or(token.len()));
+		if version.is_empty() {
+			pending_operator = operator;
+			continue;
+		}
+		let operator = if operator.is_empty() {
+			std::mem::take(&mut pending_operator)
+		} else {
+			operator
+		};
+		let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+		let (operator, version) = match version.strip_suffix('+') {
+			Some(version) if operator.is_empty() => (">=", version),
+			_ => (operator, version),
+		};
+		comparators.push(format!("{operator}{version}"));
+	}
+	comparators.join(", ")
+}
+
+/// Parses a version like [semver::Version::parse],
+/// but also accepts partial versions like `1`, `1.0` or `v1.0`.
+pub fn parse_version_lenient(s: &str) -> Result<Lenient<semver::Version>, semver::Error> {
+	let error = match semver::Version::parse(s) {
+		Ok(value) => return Ok(Lenient { value, warning: None }),
+		Err(e) => e,
+	};
+	let value = semver::Version::parse(&normalize_version(s)).map_err(|_| error)?;
+	Ok(Lenient {
+		warning: Some(format!("'{s}' is not a valid semver version, it is read as '{value}'")),
+		value,
+	})
+}
+
+/// Parses a version requirement like [semver::VersionReq::parse],
+/// but also accepts forms like `0.3.19+` or `>=0.3.19 <0.4`.
+pub fn parse_version_req_lenient(s: &str) -> Result<Lenient<semver::VersionReq>, semver::Error> {
+	let error = match semver::VersionReq::parse(s) {
+		Ok(value) => return Ok(Lenient { value, warning: None }),
+		Err(e) => e,
+	};
+	let value = semver::VersionReq::parse(&normalize_version_req(s)).map_err(|_| error)?;
+	Ok(Lenient {
+		warning: Some(format!("'{s}' is not a valid semver requirement, it is read as '{value}'")),
+		value,
+	})
+}
+
+/// Returns the lowest version allowed by the given requirement.
+pub fn version_req_lower_bound(requirement: &semver::VersionReq) -> Option<semver::Version> {
+	use semver::Op;
+	requirement
+		.comparators
+		.iter()
+		.find(|c| matches!(c.op, Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret | Op::Wildcard))
+		.map(|c| semver::Version {
+			major: c.major,
+			minor: c.minor.unwrap_or(0),
+			patch: c.patch.unwrap_or(0),
+			pre: c.pre.clone(),
+			build: semver::BuildMetadata::EMPTY,
+		})
+}
+
+/// Converts a TOML date or date-time to seconds since the unix epoch, dates without time are read as midnight UTC.
+pub fn toml_datetime_to_timestamp(datetime: &toml::value::Datetime) -> Option<i64> {
+	let date = datetime.date?;
+	// days from civil, see http://howardhinnant.github.io/date_algorithms.html
+	let year = date.year as i64 - if date.month <= 2 { 1 } else { 0 };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let month = date.month as i64;
+	let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + date.day as i64 - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	let days = era * 146097 + day_of_era - 719468;
+
+	let mut seconds = days * 86400;
+	if let Some(time) = datetime.time {
+		seconds += time.hour as i64 * 3600 + time.minute as i64 * 60 + time.second as i64;
+	}
+	if let Some(toml::value::Offset::Custom { minutes }) = datetime.offset {
+		seconds -= minutes as i64 * 60;
+	}
+	Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn version(s: &str) -> (String, bool) {
+		let version = parse_version_lenient(s).unwrap();
+		(version.value.to_string(), version.warning.is_some())
+	}
+
+	fn version_req(s: &str) -> (String, bool) {
+		let requirement = parse_version_req_lenient(s).unwrap();
+		(requirement.value.to_string(), requirement.warning.is_some())
+	}
+
+	#[test]
+	fn parses_partial_versions() {
+		assert_eq!(version("1.2.3"), ("1.2.3".to_string(), false));
+		assert_eq!(version("1"), ("1.0.0".to_string(), true));
+		assert_eq!(version("1.0"), ("1.0.0".to_string(), true));
+		assert_eq!(version("v1.2"), ("1.2.0".to_string(), true));
+		assert_eq!(version("1.0-beta.1"), ("1.0.0-beta.1".to_string(), true));
+		assert!(parse_version_lenient("latest").is_err());
+		assert!(parse_version_lenient("1.x.y").is_err());
+		assert!(parse_version_lenient("").is_err());
+	}
+
+	#[test]
+	fn parses_loose_requirements() {
+		assert_eq!(version_req(">=0.3.19"), (">=0.3.19".to_string(), false));
+		assert_eq!(version_req(">=1.0 <2"), (">=1.0, <2".to_string(), true));
+		assert_eq!(version_req(">= 1.0, < 2"), (">=1.0, <2".to_string(), false));
+		assert_eq!(version_req("0.3.19+"), (">=0.3.19".to_string(), true));
+		assert_eq!(version_req(">=v1.2"), (">=1.2".to_string(), true));
+		assert!(parse_version_req_lenient("newest").is_err());
+		assert!(parse_version_req_lenient(">=1.0 <<2").is_err());
+	}
+}
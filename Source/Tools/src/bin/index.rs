@@ -1,10 +1,14 @@
 use ficsit_networks_repository::index::PackageSchema;
 use ficsit_networks_repository::model::Readme;
 use ficsit_networks_repository::model::Readme::Markdown;
-use ficsit_networks_repository::{index, metadata, model};
+use ficsit_networks_repository::tokenizer::register_tokenizers;
+use ficsit_networks_repository::util::{parse_version_lenient, Lenient};
+use ficsit_networks_repository::reflection::Release;
+use ficsit_networks_repository::{index, lua, metadata, model, reflection};
 use getopts::Options;
-use std::fs::File;
+use std::fs::{DirEntry, File};
 use std::path::Path;
+use std::process::Command;
 use std::{env, fs};
 use tantivy::schema::*;
 use tantivy::{Index, IndexWriter};
@@ -12,16 +16,161 @@ use tempfile::TempDir;
 use zip::write::SimpleFileOptions;
 use zip::{CompressionMethod, ZipWriter};
 
+/// Returns the unix timestamps of the first and the last commit that touched the given path,
+/// [None] if git is not available or the path is not committed.
+fn git_dates(path: &Path) -> Option<(i64, i64)> {
+	let output = Command::new("git")
+		.arg("log")
+		.arg("--format=%ct")
+		.arg("--")
+		.arg(path.file_name()?)
+		.current_dir(path.parent()?)
+		.output()
+		.ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	let dates: Vec<i64> = String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.filter_map(|l| l.trim().parse().ok())
+		.collect();
+	Some((*dates.iter().min()?, *dates.iter().max()?))
+}
+
+/// The default of `--eeprom-budget`, the size in bytes an EEPROM should stay below.
+const DEFAULT_EEPROM_BUDGET: u64 = 16 * 1024;
+
+/// The checks of the EEPROMs set by the command line.
+struct Checks {
+	/// The FIN releases to check the EEPROMs against, sorted by version.
+	releases: Vec<Release>,
+	/// The size in bytes above which EEPROMs get a warning.
+	eeprom_budget: u64,
+}
+
+fn read_version(package_id: &str, entry: &DirEntry, checks: &Checks) -> Option<model::Version> {
+	if !entry.path().is_dir() {
+		return None;
+	}
+	let folder = entry.file_name().into_string().ok()?;
+	// other folders like docs or assets are no versions, so they are skipped without a warning
+	let number = folder.strip_prefix('v').unwrap_or(&folder);
+	if !number.starts_with(|c: char| c.is_ascii_digit()) {
+		return None;
+	}
+	let version = match folder.strip_prefix('v') {
+		Some(version) => parse_version_lenient(version),
+		None => parse_version_lenient(&folder).map(|version| Lenient {
+			warning: Some(format!("version folders should be prefixed with 'v', like 'v{}'", version.value)),
+			..version
+		}),
+	};
+	let version = match version {
+		Ok(version) => {
+			if let Some(warning) = version.warning {
+				println!("Package '{package_id}' version folder '{folder}': {warning}");
+			}
+			version.value
+		}
+		Err(e) => {
+			println!("Package '{package_id}' has folder '{folder}' that is ignored, because it cant be parsed as version: {e}");
+			return None;
+		}
+	};
+
+	let metadata_path = entry.path().join("metadata.toml");
+	if !metadata_path.is_file() {
+		println!("Package '{package_id}' Version '{version}' has no metadata!");
+		return None;
+	}
+	let str = fs::read_to_string(metadata_path)
+		.map_err(|e| println!("Package '{package_id}' Version '{version}' has invalid metadata: {e}"))
+		.ok()?;
+	let metadata = toml::from_str::<metadata::Version>(&str)
+		.map_err(|e| println!("Package '{package_id}' Version '{version}' has invalid metadata: {e}"))
+		.ok()?;
+	let (mut version, warnings) = model::Version::from_metadata(version.clone(), folder, metadata)
+		.map_err(|e| println!("Package '{package_id}' Version '{version}' has invalid metadata: {e}"))
+		.ok()?;
+	for warning in warnings {
+		println!("Package '{package_id}' Version '{}' metadata: {warning}", version.version);
+	}
+	let mut references = lua::References::default();
+	for eeprom in &mut version.eeprom {
+		eeprom.code = fs::read_to_string(entry.path().join(&eeprom.name))
+			.map_err(|e| println!("Package '{package_id}' Version '{}' EEPROM '{}' is not indexed: {e}", version.version, eeprom.name))
+			.ok();
+		match eeprom.code.as_deref().map(lua::parse) {
+			Some(Ok(ast)) => {
+				let minified = lua::minify(&ast);
+				eeprom.minified_size = Some(minified.len() as u64);
+				eeprom.minified = Some(minified);
+				eeprom.requirements = lua::requirements(&ast);
+				let lua::References { types, methods, properties } = lua::references(&ast);
+				references.types.extend(types);
+				references.methods.extend(methods);
+				references.properties.extend(properties);
+				version.lint.extend(lua::incompatibilities(&eeprom.name, &ast));
+			}
+			Some(Err(errors)) => version.lint.extend(lua::syntax_errors(&eeprom.name, &errors)),
+			None => {}
+		}
+		eeprom.size = eeprom.code.as_ref().map(|code| code.len() as u64);
+		if let Some(size) = eeprom.size.filter(|size| *size > checks.eeprom_budget) {
+			let minified = match eeprom.minified_size {
+				Some(minified) => format!(", {minified} bytes minified"),
+				None => String::new(),
+			};
+			println!(
+				"Package '{package_id}' Version '{}' EEPROM '{}' has {size} bytes{minified}, more than the budget of {} bytes",
+				version.version, eeprom.name, checks.eeprom_budget
+			);
+		}
+	}
+	for message in &version.lint {
+		println!("Package '{package_id}' Version '{}' {message}", version.version);
+	}
+	if !checks.releases.is_empty() {
+		check_fin_version(package_id, &version, &references, &checks.releases);
+	}
+	if let Some((first, last)) = git_dates(&entry.path()) {
+		version.published = version.published.or(Some(first));
+		version.updated = Some(last);
+	}
+	Some(version)
+}
+
+/// Warns about FIN releases of the `fin_version` requirement of the version, that lack classes, methods or properties the EEPROMs use.
+fn check_fin_version(package_id: &str, version: &model::Version, references: &lua::References, releases: &[Release]) {
+	let support = reflection::check(releases, references, version.fin_version.as_ref());
+	let prefix = format!("Package '{package_id}' Version '{}'", version.version);
+	for name in &support.unknown_types {
+		println!("{prefix} uses '{name}' that is no class or struct of any FIN release");
+	}
+	let requirement = version.fin_version.as_ref().map_or("any".to_string(), |r| format!("'{r}'"));
+	for (release, missing) in &support.missing {
+		println!("{prefix} requires {requirement} FIN version, but FIN {release} lacks {}", missing.join(", "));
+	}
+	if let Some(suggestion) = &support.suggestion {
+		println!("{prefix} should require fin_version = \"{suggestion}\"");
+	} else if support.supported.is_empty() {
+		println!("{prefix} uses classes, methods or properties no single FIN release defines");
+	}
+}
+
 fn do_index(
 	input_dir: &Path,
 	index_dir: &Path,
 	schema: Schema,
 	package_schema: &PackageSchema,
+	checks: &Checks,
 ) -> tantivy::Result<Index> {
 	let index = Index::create_in_dir(&index_dir, schema)?;
+	register_tokenizers(&index);
 
 	let mut writer: IndexWriter = index.writer(50_000_000)?;
 
+	let mut packages = Vec::new();
 	for package_folder in fs::read_dir(input_dir)? {
 		let package_folder = package_folder?;
 
@@ -32,49 +181,31 @@ fn do_index(
 			println!("Package '{package_id}' has no metadata!");
 			continue;
 		}
-		let metadata: metadata::Package =
-			match toml::from_str(fs::read_to_string(metadata_path)?.as_str()) {
-				Ok(m) => m,
-				Err(e) => {
-					println!("Package '{package_id}' has invalid metadata: {e}");
-					continue;
-				}
-			};
+		let metadata_str = fs::read_to_string(metadata_path)?;
+		let metadata = match toml::from_str::<metadata::Package>(&metadata_str) {
+			Ok(m) => m,
+			Err(e) => {
+				println!("Package '{package_id}' has invalid metadata: {e}");
+				continue;
+			}
+		};
 
 		let mut versions: Vec<_> = fs::read_dir(package_folder.path())?
-            .flatten()
-            .map(|entry| {
-                let file_name = entry.file_name();
-                let version = file_name.to_str()?.strip_prefix("v")?;
-                let version = semver::Version::parse(version).map_err(|e| println!("Package '{package_id}' has version folder '{version}' but cant be parsed as Semver: {e}")).ok()?;
-                let metadata_path = entry.path().join("metadata.toml");
-                if !metadata_path.is_file() {
-                    println!("Package '{package_id}' Version '{version}' has no metadata!");
-                    None?
-                }
-                let str = fs::read_to_string(metadata_path)
-                    .map_err(|e| println!("Package '{package_id}' Version '{version}' has invalid metadata: {e}"))
-                    .ok()?;
-                Some((version.clone(), toml::from_str::<metadata::Version>(&str).map_err(|e| println!("Package '{package_id}' Version '{version}' has invalid metadata: {e}")).ok()?))
-            })
-            .flatten()
-            .map(|(semver, v)| model::Version {
-                version: semver,
-                fin_version: v.fin_version,
-                game_version: v.game_version,
-                mod_dependencies: v.mod_dependencies.into_iter().map(|d| model::ModDependency{
-                    id: d.id,
-                    version: d.version,
-                }).collect(),
-                eeprom: v.eeprom.into_iter().map(|e| model::EEPROM{
-                    name: e.name,
-                    title: e.title,
-                    description: e.description,
-                }).collect(),
-            })
-            .collect();
+			.flatten()
+			.filter_map(|entry| read_version(&package_id, &entry, checks))
+			.collect();
 
 		versions.sort_by(|v1, v2| v2.version.cmp(&v1.version));
+		versions.dedup_by(|v2, v1| {
+			let duplicate = v1.version == v2.version;
+			if duplicate {
+				println!(
+					"Package '{package_id}' has version folders '{}' and '{}' for the same version '{}', '{}' is ignored!",
+					v1.folder, v2.folder, v1.version, v2.folder
+				);
+			}
+			duplicate
+		});
 
 		let readme_content = fs::read_to_string(package_folder.path().join("README.adoc"))
 			.map(|s| Readme::ASCIIDOC(s))
@@ -84,12 +215,40 @@ fn do_index(
 			})
 			.unwrap_or(Readme::Markdown("".to_string()));
 
-		let metadata =
-			model::Package::from_metadata(package_id, readme_content, versions, metadata);
+		let git_dates = git_dates(&package_folder.path());
+		let (mut package, warnings) =
+			match model::Package::from_metadata(package_id.clone(), readme_content, versions, metadata) {
+				Ok(package) => package,
+				Err(e) => {
+					println!("Package '{package_id}' has invalid metadata: {e}");
+					continue;
+				}
+			};
+		for warning in warnings {
+			println!("Package '{package_id}' metadata: {warning}");
+		}
+		if let Some(version) = &package.version {
+			if !package.versions.iter().any(|v| v.version == *version) {
+				println!("Package '{package_id}' has version '{version}' in its metadata, but no version folder 'v{version}'");
+			}
+		}
+		package.published = package
+			.versions
+			.iter()
+			.filter_map(|v| v.published)
+			.min()
+			.or(git_dates.map(|(first, _)| first));
+		package.updated = git_dates
+			.map(|(_, last)| last)
+			.into_iter()
+			.chain(package.versions.iter().filter_map(|v| v.updated.or(v.published)))
+			.max();
 
-		index::add_package_to_index(&mut writer, package_schema, metadata).unwrap();
+		packages.push(package);
 	}
 
+	index::add_packages_to_index(&writer, package_schema, packages)?;
+
 	writer.commit()?;
 
 	Ok(index)
@@ -106,18 +265,41 @@ fn zip_index(index_dir: &Path, index: &Index, output_file: &File) -> zip::result
 	zip.finish().map(|_| ())
 }
 
-fn cmd_index(input_dir: &Path, output_file: &Path) {
+fn cmd_index(input_dir: &Path, output_file: &Path, checks: &Checks) {
 	let out_file = File::create(output_file).unwrap();
 
 	let (schema, package_schema) = ficsit_networks_repository::index::build_schema();
 
 	let index_dir = TempDir::new().unwrap();
 
-	let index = do_index(input_dir, index_dir.path(), schema, &package_schema).unwrap();
+	let index = do_index(input_dir, index_dir.path(), schema, &package_schema, checks).unwrap();
 
 	zip_index(index_dir.path(), &index, &out_file).unwrap();
 }
 
+/// Reads the reflection documentation files given like `0.3.19=docs.json`, sorted by release.
+fn read_releases(files: &[String]) -> Vec<Release> {
+	let mut releases: Vec<Release> = files
+		.iter()
+		.map(|arg| {
+			let Some((version, file)) = arg.split_once('=') else {
+				panic!("--reflection: expected VERSION=FILE, got '{arg}'");
+			};
+			let version = match parse_version_lenient(version) {
+				Ok(version) => version.value,
+				Err(e) => panic!("--reflection: invalid version '{version}': {e}"),
+			};
+			let json = fs::read_to_string(file).unwrap();
+			match Release::from_json(version, &json) {
+				Ok(release) => release,
+				Err(e) => panic!("--reflection: {file}: {e}"),
+			}
+		})
+		.collect();
+	releases.sort_by(|r1, r2| r1.version.cmp(&r2.version));
+	releases
+}
+
 fn main() {
 	let args: Vec<String> = env::args().collect();
 	let program = args[0].clone();
@@ -125,6 +307,8 @@ fn main() {
 	let mut opts = Options::new();
 	opts.optopt("o", "output", "set the output file", "NAME")
 		.optopt("i", "input", "set the input directory", "NAME")
+		.optopt("", "eeprom-budget", &format!("warn about EEPROMs larger than this many bytes (default {DEFAULT_EEPROM_BUDGET})"), "BYTES")
+		.optmulti("r", "reflection", "check the EEPROMs against the reflection documentation FIN exported for a release", "VERSION=FILE")
 		.optflag("h", "help", "print this help menu");
 	let matches = match opts.parse(&args[1..]) {
 		Ok(m) => m,
@@ -138,5 +322,14 @@ fn main() {
 	let output = matches.opt_str("o").unwrap_or("./index.zip".to_string());
 	let input = matches.opt_str("i").unwrap_or("./Packages".to_string());
 
-	cmd_index(Path::new(&input), Path::new(&output));
+	let eeprom_budget = match matches.opt_str("eeprom-budget").map(|b| b.parse()).transpose() {
+		Ok(budget) => budget.unwrap_or(DEFAULT_EEPROM_BUDGET),
+		Err(e) => panic!("--eeprom-budget: {e}"),
+	};
+	let checks = Checks {
+		releases: read_releases(&matches.opt_strs("reflection")),
+		eeprom_budget,
+	};
+
+	cmd_index(Path::new(&input), Path::new(&output), &checks);
 }
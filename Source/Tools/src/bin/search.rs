@@ -1,16 +1,54 @@
-use ficsit_networks_repository::index;
+use ficsit_networks_repository::compat::QueryVersions;
+use ficsit_networks_repository::game::parse_game_version;
 use ficsit_networks_repository::index::load_schema;
-use getopts::Options;
+use ficsit_networks_repository::search::{search, EepromHit, FieldBoosts, Highlight, InvalidQuery, SearchError, SearchRequest};
+use ficsit_networks_repository::smm::parse_smm;
+use ficsit_networks_repository::tokenizer::register_tokenizers;
+use ficsit_networks_repository::util::parse_version_lenient;
+use getopts::{Matches, Options};
+use serde::Serialize;
 use std::fs::File;
-use std::path::{Path, PathBuf};
-use std::{env, io};
-use tantivy::collector::{BytesFilterCollector, TopDocs};
-use tantivy::query::{FuzzyTermQuery, QueryParser};
-use tantivy::schema::*;
-use tantivy::{doc, version, Index, IndexWriter, ReloadPolicy};
+use std::path::Path;
+use std::str::FromStr;
+use std::{env, io, process};
+use tantivy::{Index, ReloadPolicy};
 use tempfile::TempDir;
 use zip::ZipArchive;
 
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+	Table,
+	Json,
+	Ids,
+}
+
+impl FromStr for Format {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"table" => Ok(Format::Table),
+			"json" => Ok(Format::Json),
+			"ids" => Ok(Format::Ids),
+			_ => Err(format!("unknown format '{s}', expected 'table', 'json' or 'ids'")),
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct SearchHit {
+	id: String,
+	score: Option<f32>,
+	latest: Option<String>,
+	versions: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	explanation: Option<serde_json::Value>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	highlight: Option<Highlight>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	eeproms: Vec<EepromHit>,
+}
+
 fn unzip_index(index_file: &Path) -> zip::result::ZipResult<TempDir> {
 	let index = File::open(index_file)?;
 
@@ -36,13 +74,13 @@ fn unzip_index(index_file: &Path) -> zip::result::ZipResult<TempDir> {
 }
 
 fn load_index(index_dir: &Path) -> tantivy::Result<Index> {
-	Index::open_in_dir(index_dir)
+	let index = Index::open_in_dir(index_dir)?;
+	register_tokenizers(&index);
+	Ok(index)
 }
 
-fn do_query(index: &Index, query: &str) -> tantivy::Result<()> {
-	let schema = index.schema();
-
-	let package_schema = load_schema(&schema)?;
+fn do_query(index: &Index, request: &SearchRequest) -> Result<Vec<SearchHit>, SearchError> {
+	let package_schema = load_schema(&index.schema())?;
 
 	let reader = index
 		.reader_builder()
@@ -51,67 +89,173 @@ fn do_query(index: &Index, query: &str) -> tantivy::Result<()> {
 
 	let searcher = reader.searcher();
 
-	let query_parser = QueryParser::for_index(
-		&index,
-		vec![
-			package_schema.id,
-			package_schema.name,
-			package_schema.short_description,
-			package_schema.readme,
-			package_schema.tags,
-			package_schema.authors,
-			package_schema.versions,
-		],
-	);
-
-	let query = query_parser.parse_query(query)?;
-
-	let version_filter = BytesFilterCollector::new(
-		"version_data".to_string(),
-		|bytes: &[u8]| {
-			if let Ok(version_data) = bitcode::decode::<index::VersionData>(bytes)
-				.map_err(|e| println!("Error at decoding Version Data: {e}"))
-			{
-				if let Some(fin_version) = version_data.fin_version {
-					let fin_version = semver::VersionReq::parse(&fin_version).unwrap();
-					fin_version.matches(&semver::Version::new(0, 3, 19))
-				} else {
-					false
-				}
-			} else {
-				false
-			}
-		},
-		TopDocs::with_limit(10),
-	);
+	let result = search(&searcher, &package_schema, request)?;
+	if let Some(invalid) = &result.query_errors {
+		eprintln!("Ignored invalid parts of the query:");
+		print_query_errors(invalid);
+	}
+	if let Some(suggestion) = &result.suggestion {
+		eprintln!("Did you mean: {suggestion}");
+	}
+	eprintln!("{} of {} results", result.hits.len(), result.total);
+	if let Some(cursor) = &result.next_cursor {
+		eprintln!("Next page: --cursor {cursor}");
+	}
 
-	let top_docs = searcher.search(&query, &version_filter)?;
-	//let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+	Ok(result
+		.hits
+		.into_iter()
+		.map(|hit| SearchHit {
+			id: hit.id,
+			score: hit.score,
+			latest: hit.version.map(|v| v.to_string()),
+			versions: hit.versions.iter().map(|(v, _)| v.to_string()).collect(),
+			explanation: hit
+				.explanation
+				.map(|e| serde_json::from_str(&e).unwrap_or_default()),
+			highlight: hit.highlight,
+			eeproms: hit.eeproms,
+		})
+		.collect())
+}
 
-	for (_score, doc_address) in top_docs {
-		let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
-		println!("{}", retrieved_doc.to_json(&schema));
+/// Prints the errors with a marker below the position in the query.
+fn print_query_errors(invalid: &InvalidQuery) {
+	for error in &invalid.errors {
+		eprintln!("{}", error.message);
+		if let Some(position) = error.position {
+			let column = invalid.query[..position].chars().count();
+			eprintln!("  {}\n  {}^", invalid.query, " ".repeat(column));
+		}
+	}
+	if let Some(suggestion) = &invalid.suggestion {
+		eprintln!("Did you mean: {suggestion}");
 	}
+}
 
-	/*let query = query_parser.parse_query("title:sea^20 body:whale^70")?;
+fn print_hits(hits: &[SearchHit], format: Format) {
+	match format {
+		Format::Ids => {
+			for hit in hits {
+				println!("{}", hit.id);
+			}
+		}
+		Format::Json => println!("{}", serde_json::to_string_pretty(hits).unwrap()),
+		Format::Table => {
+			let id_width = hits.iter().map(|h| h.id.len()).max().unwrap_or(0).max(2);
+			println!("{:<id_width$}  {:<10}  {:>8}", "ID", "LATEST", "SCORE");
+			for hit in hits {
+				let score = hit.score.map(|s| format!("{s:.3}")).unwrap_or("-".to_string());
+				println!(
+					"{:<id_width$}  {:<10}  {:>8}",
+					hit.id,
+					hit.latest.as_deref().unwrap_or("-"),
+					score
+				);
+				if let Some(highlight) = &hit.highlight {
+					println!("  {}: {}", highlight.field, highlight.fragment.replace('\n', " "));
+				}
+				for eeprom in &hit.eeproms {
+					println!("  eeprom: {} (v{}) {}", eeprom.file, eeprom.version, eeprom.title);
+					if !eeprom.requirements.is_empty() {
+						println!("    requires: {}", eeprom.requirements.join(", "));
+					}
+					if let Some(highlight) = &eeprom.highlight {
+						println!("    {}: {}", highlight.field, highlight.fragment.replace('\n', " "));
+					}
+				}
+				if let Some(explanation) = &hit.explanation {
+					println!("{}", serde_json::to_string_pretty(explanation).unwrap());
+				}
+			}
+		}
+	}
+}
 
-	let (_score, doc_address) = searcher
-		.search(&query, &TopDocs::with_limit(1))?
-		.into_iter()
-		.next()
-		.unwrap();
+fn cmd_search(index_file: &Path, request: &SearchRequest, format: Format) {
+	let index_dir = unzip_index(index_file).unwrap();
+	let index = load_index(index_dir.path()).unwrap();
+	match do_query(&index, request) {
+		Ok(hits) => print_hits(&hits, format),
+		Err(SearchError::Query(invalid)) => {
+			eprintln!("Invalid query:");
+			print_query_errors(&invalid);
+			process::exit(1);
+		}
+		Err(e) => fail(e),
+	}
+}
 
-	let explanation = query.explain(&searcher, doc_address)?;
+/// Prints the error of a bad argument or failed search and exits with a failure code.
+fn fail(message: impl std::fmt::Display) -> ! {
+	eprintln!("{message}");
+	process::exit(1)
+}
 
-	println!("{}", explanation.to_pretty_json());*/
+fn parse_opt<T: FromStr>(matches: &Matches, name: &str) -> Option<T>
+where
+	T::Err: std::fmt::Display,
+{
+	match matches.opt_str(name).map(|v| v.parse()).transpose() {
+		Ok(v) => v,
+		Err(e) => fail(format!("--{name}: {e}")),
+	}
+}
 
-	Ok(())
+fn parse_version(name: &str, version: &str) -> semver::Version {
+	match parse_version_lenient(version) {
+		Ok(version) => version.value,
+		Err(e) => fail(format!("--{name}: invalid version '{version}': {e}")),
+	}
 }
 
-fn cmd_search(index_file: &Path, query: &str) {
-	let index_dir = unzip_index(index_file).unwrap();
-	let index = load_index(index_dir.path()).unwrap();
-	do_query(&index, query).unwrap();
+fn query_versions(matches: &Matches) -> QueryVersions {
+	let mut versions = match matches.opt_str("smm") {
+		Some(file) => {
+			let json = std::fs::read_to_string(&file).unwrap();
+			match parse_smm(&json, matches.opt_str("profile").as_deref()) {
+				Ok(import) => import.query_versions(),
+				Err(e) => fail(format!("{file}: {e}")),
+			}
+		}
+		None => QueryVersions::default(),
+	};
+	if let Some(fin_version) = matches.opt_str("fin-version") {
+		versions.fin_version = Some(parse_version("fin-version", &fin_version));
+	}
+	if let Some(game_version) = matches.opt_str("game-version") {
+		versions.game_version = match parse_game_version(&game_version) {
+			Ok(version) => Some(version),
+			Err(e) => fail(format!("--game-version: {e}")),
+		};
+	}
+	for m in matches.opt_strs("mod") {
+		let (id, version) = match m.split_once('=') {
+			Some((id, version)) => (id, Some(parse_version("mod", version))),
+			None => (m.as_str(), None),
+		};
+		versions.mods.insert(id.to_string(), version);
+	}
+	versions.check_mods |= matches.opt_present("check-mods") || matches.opt_present("mod");
+	versions.channel = parse_opt(matches, "channel").unwrap_or_default();
+	versions
+}
+
+fn field_boosts(matches: &Matches) -> FieldBoosts {
+	let mut boosts = FieldBoosts::default();
+	for b in matches.opt_strs("boost") {
+		let result = match b.split_once('=') {
+			Some((field, boost)) => match boost.parse() {
+				Ok(boost) => boosts.set(field, boost),
+				Err(e) => Err(format!("invalid boost '{boost}': {e}")),
+			},
+			None => Err(format!("expected FIELD=BOOST, got '{b}'")),
+		};
+		if let Err(e) = result {
+			fail(format!("--boost: {e}"));
+		}
+	}
+	boosts
 }
 
 fn main() {
@@ -120,10 +264,26 @@ fn main() {
 
 	let mut opts = Options::new();
 	opts.optopt("i", "input", "set the search index file", "NAME")
+		.optopt("c", "channel", "set the release channel (stable or beta)", "CHANNEL")
+		.optopt("", "fin-version", "only show packages compatible with this FIN version", "VERSION")
+		.optopt("", "game-version", "only show packages compatible with this game version, release or build", "VERSION")
+		.optmulti("m", "mod", "an installed mod, enables the mod dependency check", "ID=VERSION")
+		.optflag("", "check-mods", "check mod dependencies even if no mod is installed")
+		.optopt("", "smm", "filter by the mods of a Satisfactory Mod Manager lockfile or profile file", "FILE")
+		.optopt("", "profile", "set the profile to use of the --smm file", "NAME")
+		.optopt("n", "limit", "set the maximum number of results (default 10, at most 100)", "COUNT")
+		.optopt("", "offset", "skip the first results", "COUNT")
+		.optopt("", "cursor", "continue after the last result of a previous search", "CURSOR")
+		.optopt("s", "sort", "set the order: relevance (default), name, newest, updated or popular", "SORT")
+		.optopt("f", "format", "set the output format: table (default), json or ids", "FORMAT")
+		.optflag("", "exact", "do not match misspelled words or prefixes")
+		.optflag("", "lenient", "ignore the invalid parts of the query")
+		.optmulti("b", "boost", "set how much matches in a field count, like name=3", "FIELD=BOOST")
+		.optflag("e", "explain", "explain the score of each result")
 		.optflag("h", "help", "print this help menu");
 	let matches = match opts.parse(&args[1..]) {
 		Ok(m) => m,
-		Err(f) => panic!("{}", f.to_string()),
+		Err(f) => fail(f),
 	};
 	if matches.opt_present("h") {
 		let brief = format!("Usage: {} [options] QUERY", program);
@@ -131,11 +291,20 @@ fn main() {
 		return;
 	}
 	let input = matches.opt_str("i").unwrap_or("./index.zip".to_string());
-	let query = if matches.free.is_empty() {
-		"*".to_string()
-	} else {
-		matches.free.join(" ")
+	let request = SearchRequest {
+		query: matches.free.join(" "),
+		versions: query_versions(&matches),
+		limit: parse_opt(&matches, "limit").unwrap_or(10),
+		offset: parse_opt(&matches, "offset").unwrap_or(0),
+		cursor: parse_opt(&matches, "cursor"),
+		sort: parse_opt(&matches, "sort").unwrap_or_default(),
+		fuzzy: !matches.opt_present("exact"),
+		boosts: field_boosts(&matches),
+		explain: matches.opt_present("explain"),
+		lenient: matches.opt_present("lenient"),
+		..Default::default()
 	};
+	let format = parse_opt(&matches, "format").unwrap_or(Format::Table);
 
-	cmd_search(&Path::new(&input), &query);
+	cmd_search(Path::new(&input), &request, format);
 }
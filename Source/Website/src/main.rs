@@ -7,7 +7,7 @@ mod util;
 
 use crate::repository::Repository;
 use axum::middleware::from_fn;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::Router;
 use tower_http::compression::CompressionLayer;
 use tower_http::trace::TraceLayer;
@@ -36,6 +36,11 @@ pub async fn app() -> Result<Router, anyhow::Error> {
 		.route("/", get(routes::get_index))
 		.route("/privacy-policy", get(routes::privacy_policy))
 		.route("/package/:id", get(routes::package::get_package))
+		.route("/package/:id/eeprom/:file", get(routes::package::get_eeprom))
+		.route("/suggest", get(routes::suggest::get_suggest))
+		.route("/updates", post(routes::updates::post_updates))
+		.route("/import/smm", post(routes::import::post_import_smm))
+		.route("/import/save", post(routes::import::post_import_save))
 		.layer(from_fn(routes::middleware::get_htmx_header))
 		.layer(from_fn(routes::middleware::accept_json_only))
 		.layer(TraceLayer::new_for_http())
@@ -1,27 +1,29 @@
 use crate::util::{read_file_or_url, read_file_or_url_as_string, URLOrFile};
 use axum::http::StatusCode;
-use ficsit_networks_repository::index::{load_schema, PackageSchema};
+use ficsit_networks_repository::compat::{check_update, latest_compatible_version, QueryVersions, UpdateCheck};
+use ficsit_networks_repository::index::{load_schema, PackageSchema, VersionData};
 use ficsit_networks_repository::model;
 use ficsit_networks_repository::model::Readme;
+use ficsit_networks_repository::resolve::{IndexSource, ResolvedPackage, Resolver};
+use ficsit_networks_repository::search;
+use ficsit_networks_repository::search::{Completion, SearchError, SearchHit, SearchRequest, SearchResult};
+use ficsit_networks_repository::tokenizer::register_tokenizers;
 use ficsit_networks_repository::{index, metadata};
-use futures_util::future::{join_all, try_join_all};
+use futures_util::future::try_join_all;
 use moka::future::{Cache, CacheBuilder};
 use std::borrow::Borrow;
 use std::io::{Read, Seek};
-use std::ops::Deref;
 use std::path::Path;
 use std::sync::Arc;
 use tantivy::collector::TopDocs;
-use tantivy::query::Occur;
 use tantivy::schema::{IndexRecordOption, Value};
-use tantivy::{query, Index, Score, Term};
+use tantivy::{query, Index, TantivyError, Term};
 use tempfile::TempDir;
 use tokio::try_join;
 use zip::ZipArchive;
 
 #[derive(Clone)]
 pub struct Repository {
-	pub index: Arc<Index>,
 	pub package_schema: Arc<PackageSchema>,
 	pub reader: tantivy::IndexReader,
 	pub raw_url: String,
@@ -59,7 +61,9 @@ async fn get_and_unzip_index(url: &str) -> zip::result::ZipResult<TempDir> {
 }
 
 fn load_index(index_dir: &Path) -> tantivy::Result<Index> {
-	Index::open_in_dir(index_dir)
+	let index = Index::open_in_dir(index_dir)?;
+	register_tokenizers(&index);
+	Ok(index)
 }
 
 impl Repository {
@@ -68,7 +72,6 @@ impl Repository {
 		let index = load_index(index_file.path()).unwrap();
 		let schema = index.schema();
 		let package_schema = load_schema(&schema).unwrap();
-		let index = Arc::new(index);
 		let reader = index.reader_builder().try_into().unwrap();
 		let package_meta_cache = CacheBuilder::new(10_000)
 			.time_to_live(std::time::Duration::new(30, 0))
@@ -78,7 +81,6 @@ impl Repository {
 			.build();
 
 		Repository {
-			index,
 			package_schema: Arc::new(package_schema),
 			reader,
 			raw_url,
@@ -91,41 +93,163 @@ impl Repository {
 		self.raw_url.clone() + path
 	}
 
-	pub async fn get_package_index_data_by_id(&self, id: String) -> Option<Vec<semver::Version>> {
+	pub async fn get_package_index_data_by_id(
+		&self,
+		id: String,
+	) -> Option<Vec<(semver::Version, VersionData)>> {
 		let package_schema = self.package_schema.clone();
 		let searcher = self.reader.searcher();
 		tokio::task::spawn_blocking(move || {
-			let query = query::BooleanQuery::new(vec![(
-				Occur::Must,
-				Box::new(query::TermQuery::new(
-					Term::from_field_text(package_schema.id, &id),
-					IndexRecordOption::Basic,
-				)),
-			)]);
-			if let [(_, address)] = searcher.search(&query, &TopDocs::with_limit(1)).ok()?[..] {
-				let doc: tantivy::TantivyDocument = searcher.doc(address).ok()?;
-				Some(
-					doc.get_all(package_schema.versions)
-						.map(|v| semver::Version::parse(v.as_str()?).ok())
-						.flatten()
-						.collect(),
-				)
-			} else {
-				None
-			}
+			index::get_package_versions(&searcher, &package_schema, &id)
+		})
+		.await
+		.ok()
+		.flatten()
+	}
+
+	pub async fn get_latest_compatible_version(
+		&self,
+		id: String,
+		query: QueryVersions,
+	) -> Option<semver::Version> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || {
+			let versions = index::get_package_versions(&searcher, &package_schema, &id)?;
+			latest_compatible_version(&query, &versions).cloned()
+		})
+		.await
+		.ok()
+		.flatten()
+	}
+
+	/// Checks the installed versions of packages for updates, [None] for packages that do not exist.
+	pub async fn check_updates(
+		&self,
+		packages: Vec<(String, semver::Version)>,
+		query: QueryVersions,
+	) -> Vec<Option<UpdateCheck>> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		let count = packages.len();
+		tokio::task::spawn_blocking(move || {
+			packages
+				.iter()
+				.map(|(id, installed)| {
+					let versions = index::get_package_versions(&searcher, &package_schema, id)?;
+					Some(check_update(&query, installed, &versions))
+				})
+				.collect()
+		})
+		.await
+		.unwrap_or_else(|_| vec![None; count])
+	}
+
+	/// Resolves the dependencies of a package version, the version itself is the first entry.
+	pub async fn resolve_dependencies(
+		&self,
+		id: String,
+		version: semver::Version,
+		query: QueryVersions,
+	) -> Result<Vec<ResolvedPackage>, String> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || {
+			let source = IndexSource {
+				searcher: &searcher,
+				package_schema: &package_schema,
+			};
+			let requirement = semver::VersionReq::parse(&format!("={version}")).map_err(|e| e.to_string())?;
+			Resolver::new(&source, &query)
+				.resolve(&id, Some(requirement))
+				.map_err(|e| e.to_string())
+		})
+		.await
+		.map_err(|e| e.to_string())?
+	}
+
+	/// Searches the packages, see [search::search].
+	pub async fn search(&self, request: SearchRequest) -> Result<SearchResult, SearchError> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || search::search(&searcher, &package_schema, &request))
+			.await
+			.map_err(|e| SearchError::Index(TantivyError::InternalError(e.to_string())))?
+	}
+
+	/// Finds packages similar to the given one, see [search::related].
+	pub async fn related(
+		&self,
+		id: String,
+		versions: QueryVersions,
+		limit: usize,
+	) -> tantivy::Result<Vec<SearchHit>> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || search::related(&searcher, &package_schema, &id, &versions, limit))
+			.await
+			.map_err(|e| TantivyError::InternalError(e.to_string()))?
+	}
+
+	/// Completes the given prefix to package names, tags and authors, see [search::complete].
+	pub async fn complete(&self, prefix: String, limit: usize) -> tantivy::Result<Vec<Completion>> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || search::complete(&searcher, &package_schema, &prefix, limit))
+			.await
+			.map_err(|e| TantivyError::InternalError(e.to_string()))?
+	}
+
+	/// Returns the code of an EEPROM file of a package version, or its minified code.
+	pub async fn get_eeprom_code(
+		&self,
+		id: String,
+		version: semver::Version,
+		file: String,
+		minified: bool,
+	) -> Option<String> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || {
+			index::get_eeprom_code(&searcher, &package_schema, &id, &version, &file, minified)
+		})
+		.await
+		.ok()
+		.flatten()
+	}
+
+	/// Returns the ids of all packages that provide the given capability in any of their versions.
+	pub async fn get_packages_providing(&self, capability: String) -> Vec<String> {
+		let package_schema = self.package_schema.clone();
+		let searcher = self.reader.searcher();
+		tokio::task::spawn_blocking(move || {
+			let query = query::TermQuery::new(
+				Term::from_field_text(package_schema.provides, &capability),
+				IndexRecordOption::Basic,
+			);
+			searcher
+				.search(&query, &TopDocs::with_limit(100))
+				.ok()?
+				.into_iter()
+				.map(|(_, address)| {
+					let doc: tantivy::TantivyDocument = searcher.doc(address).ok()?;
+					Some(doc.get_first(package_schema.id)?.as_str()?.to_string())
+				})
+				.collect()
 		})
 		.await
 		.ok()
 		.flatten()
+		.unwrap_or_default()
 	}
 
 	pub async fn get_version_meta(
 		&self,
 		id: &str,
-		version: &str,
+		folder: &str,
 	) -> Result<metadata::Version, StatusCode> {
 		let s = read_file_or_url_as_string(
-			&self.path(&format!("/Packages/{id}/v{version}/metadata.toml")),
+			&self.path(&format!("/Packages/{id}/{folder}/metadata.toml")),
 		)
 		.await
 		.ok_or(StatusCode::NOT_FOUND)?;
@@ -178,31 +302,18 @@ impl Repository {
 							.await
 							.ok_or(StatusCode::NOT_FOUND)?
 							.into_iter()
-							.map(|version| async {
-								let metadata =
-									self.get_version_meta(id, &version.to_string()).await?;
-								Ok(model::Version {
-									version,
-									fin_version: metadata.fin_version,
-									game_version: metadata.game_version,
-									mod_dependencies: metadata
-										.mod_dependencies
-										.into_iter()
-										.map(|m| model::ModDependency {
-											id: m.id,
-											version: m.version,
-										})
-										.collect(),
-									eeprom: metadata
-										.eeprom
-										.into_iter()
-										.map(|e| model::EEPROM {
-											name: e.name,
-											title: e.title,
-											description: e.description,
-										})
-										.collect(),
-								})
+							.map(|(version, data)| async move {
+								let metadata = self.get_version_meta(id, &data.folder).await?;
+								let (mut version, _) = model::Version::from_metadata(version, data.folder, metadata)
+									.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+								version.lint = data.lint;
+								for eeprom in &mut version.eeprom {
+									if let Some(size) = data.eeprom_sizes.iter().find(|s| s.file == eeprom.name) {
+										eeprom.size = Some(size.size);
+										eeprom.minified_size = size.minified;
+									}
+								}
+								Ok(version)
 							}),
 					)
 					.await
@@ -210,12 +321,14 @@ impl Repository {
 
 				let (metadata, readme, versions) = try_join!(metadata, readme, versions)?;
 
-				Ok(model::Package::from_metadata(
+				let (package, _) = model::Package::from_metadata(
 					id.to_string(),
 					readme,
 					versions,
 					metadata,
-				))
+				)
+				.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+				Ok(package)
 			})
 			.await
 			.map_err(|e| *e)
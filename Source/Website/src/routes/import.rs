@@ -0,0 +1,117 @@
+use crate::routes::middleware::AcceptJsonOnly;
+use axum::extract::Multipart;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response, Result};
+use axum::{Extension, Json};
+use ficsit_networks_repository::compat::QueryVersions;
+use ficsit_networks_repository::save::{parse_save_header, SaveError};
+use ficsit_networks_repository::smm::parse_smm;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How much of an uploaded save we read at most to find the end of its header.
+const MAX_SAVE_HEADER_SIZE: usize = 1 << 20;
+
+#[derive(Serialize)]
+pub struct ImportResponse {
+	pub fin_version: Option<String>,
+	pub game_version: Option<String>,
+	pub mods: HashMap<String, Option<String>>,
+	/// The package list URL filtered by the imported environment.
+	pub url: String,
+}
+
+fn import_response(json: bool, query: QueryVersions) -> Response {
+	let mut params = url::form_urlencoded::Serializer::new(String::new());
+	if let Some(fin_version) = &query.fin_version {
+		params.append_pair("fin_version", &fin_version.to_string());
+	}
+	if let Some(game_version) = &query.game_version {
+		params.append_pair("game_version", &game_version.to_string());
+	}
+	if query.check_mods {
+		params.append_pair("check_mods", "true");
+	}
+	let mut mods: Vec<_> = query.mods.iter().collect();
+	mods.sort_by_key(|(id, _)| *id);
+	for (id, version) in mods {
+		let version = version.as_ref().map(|v| v.to_string()).unwrap_or_default();
+		params.append_pair(&format!("mod_{id}"), &version);
+	}
+	let url = format!("/?{}", params.finish());
+
+	if json {
+		Json(ImportResponse {
+			fin_version: query.fin_version.map(|v| v.to_string()),
+			game_version: query.game_version.map(|v| v.to_string()),
+			mods: query
+				.mods
+				.into_iter()
+				.map(|(id, version)| (id, version.map(|v| v.to_string())))
+				.collect(),
+			url,
+		})
+		.into_response()
+	} else {
+		Redirect::to(&url).into_response()
+	}
+}
+
+/// Takes a mod manager lockfile or profile file as `file` field and an optional `profile` name,
+/// and redirects to the package list filtered by the mods found in it.
+pub async fn post_import_smm(
+	Extension(AcceptJsonOnly(json)): Extension<AcceptJsonOnly>,
+	mut multipart: Multipart,
+) -> Result<Response> {
+	let mut file = None;
+	let mut profile = None;
+	while let Some(field) = multipart
+		.next_field()
+		.await
+		.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+	{
+		let name = field.name().map(str::to_string);
+		let text = field
+			.text()
+			.await
+			.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+		match name.as_deref() {
+			Some("file") => file = Some(text),
+			Some("profile") if !text.trim().is_empty() => profile = Some(text.trim().to_string()),
+			_ => {}
+		}
+	}
+	let file = file.ok_or((StatusCode::BAD_REQUEST, "Missing field 'file'".to_string()))?;
+	let import = parse_smm(&file, profile.as_deref()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+	Ok(import_response(json, import.query_versions()))
+}
+
+/// Takes a save file as `file` field and redirects to the package list filtered by the game build and mods of the save.
+/// Only the header at the start of the file is read, the rest of the upload is ignored.
+pub async fn post_import_save(
+	Extension(AcceptJsonOnly(json)): Extension<AcceptJsonOnly>,
+	mut multipart: Multipart,
+) -> Result<Response> {
+	let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+	while let Some(mut field) = multipart
+		.next_field()
+		.await
+		.map_err(|e| bad_request(e.to_string()))?
+	{
+		if field.name() != Some("file") {
+			continue;
+		}
+		let mut data = Vec::new();
+		while let Some(chunk) = field.chunk().await.map_err(|e| bad_request(e.to_string()))? {
+			data.extend_from_slice(&chunk);
+			match parse_save_header(&data) {
+				Ok(header) => return Ok(import_response(json, header.query_versions())),
+				Err(SaveError::Incomplete) if data.len() < MAX_SAVE_HEADER_SIZE => {}
+				Err(e) => return Err(bad_request(e.to_string()).into()),
+			}
+		}
+		return Err(bad_request(SaveError::Incomplete.to_string()).into());
+	}
+	Err(bad_request("Missing field 'file'".to_string()).into())
+}
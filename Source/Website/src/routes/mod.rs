@@ -1,25 +1,31 @@
+pub mod import;
 pub mod middleware;
 pub mod package;
+pub mod suggest;
+pub mod updates;
 
 use crate::repository::Repository;
 use crate::routes::middleware::{AcceptJsonOnly, HTMXExtension};
 use crate::templates::package::ListPackageResponse;
-use crate::templates::{GetIndexResponse, GetPrivacyPolicyResponse, PackageCard};
+use crate::templates::{
+	FacetChip, FacetGroup, GetIndexResponse, GetPrivacyPolicyResponse, InvalidQueryJsonResponse,
+	ListPackageJsonResponse, PackageCard, SortOption,
+};
 use askama_axum::IntoResponse;
-use axum::extract::{Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::extract::{Query, RawQuery, State};
+use axum::http::StatusCode;
 use axum::response::Response;
-use axum::{http, Extension, Json};
-use ficsit_networks_repository::index;
-use ficsit_networks_repository::index::VersionData;
-use futures_util::future::{join_all, try_join_all};
-use serde::{Deserialize, Serialize};
+use axum::{Extension, Json};
+use ficsit_networks_repository::compat::QueryVersions;
+use ficsit_networks_repository::game::parse_game_version;
+use ficsit_networks_repository::search::{
+	FacetCount, SearchError, SearchRequest, SearchResult, Sort, MAX_PAGE_SIZE,
+};
+use ficsit_networks_repository::util::parse_version_lenient;
+use futures_util::future::join_all;
+use serde::Deserialize;
 use std::collections::HashMap;
-use std::str::FromStr;
-use tantivy::collector::{BytesFilterCollector, TopDocs};
-use tantivy::query;
-use tantivy::query::QueryClone;
-use tantivy::schema::Value;
+use tantivy::schema::Facet;
 
 #[derive(Deserialize)]
 pub struct SearchQuery {
@@ -27,90 +33,119 @@ pub struct SearchQuery {
 	check_mods: Option<bool>,
 	game_version: Option<String>,
 	fin_version: Option<String>,
+	provides: Option<String>,
+	channel: Option<String>,
+	sort: Option<String>,
+	/// Ignores invalid parts of the query instead of failing, always on for HTML.
+	lenient: Option<bool>,
 	#[serde(flatten)]
 	other: HashMap<String, Option<String>>,
 }
 
-#[derive(Deserialize)]
-pub struct Pagination {
-	page: Option<usize>,
-	page_size: Option<usize>,
+/// The facet filters of a query string, given as repeated `facet` parameters like `facet=/tags/power`.
+fn parse_facets(query: Option<&str>) -> Vec<String> {
+	url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+		.filter(|(k, _)| k == "facet")
+		.map(|(_, v)| v.into_owned())
+		.filter(|v| Facet::from_text(v).is_ok())
+		.collect()
 }
 
-#[derive(Clone)]
-struct QueryVersions {
-	fin_version: Option<semver::Version>,
-	game_version: Option<semver::Version>,
-	check_mods: bool,
-	mods: HashMap<String, Option<semver::Version>>,
+/// Builds the chips of a facet group, each one links to the current query with its facet toggled.
+fn facet_group(name: &str, query: Option<&str>, active: &[String], counts: &[FacetCount]) -> FacetGroup {
+	let params: Vec<_> = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+		.filter(|(k, _)| k != "facet" && k != "page")
+		.collect();
+	let chips = counts
+		.iter()
+		.map(|count| {
+			let is_active = active.contains(&count.facet);
+			let mut url = url::form_urlencoded::Serializer::new(String::new());
+			url.extend_pairs(params.iter());
+			for facet in active.iter().filter(|f| **f != count.facet) {
+				url.append_pair("facet", facet);
+			}
+			if !is_active {
+				url.append_pair("facet", &count.facet);
+			}
+			FacetChip {
+				label: count.value.clone(),
+				count: count.count,
+				active: is_active,
+				url: format!("/?{}", url.finish()),
+			}
+		})
+		.collect();
+	FacetGroup {
+		name: name.to_string(),
+		chips,
+	}
 }
 
-impl QueryVersions {
-	fn parse_search_query(s: &SearchQuery) -> Self {
-		let fin_version = s
-			.fin_version
-			.as_ref()
-			.map(|s| semver::Version::parse(s).ok())
-			.flatten();
-		let game_version = s
-			.game_version
-			.as_ref()
-			.map(|s| semver::Version::parse(s).ok())
-			.flatten();
-		let check_mods = s.check_mods.unwrap_or(false);
-		let mods = s
-			.other
-			.iter()
-			.map(|(k, v)| Some((k.strip_prefix("mod_")?, v)))
-			.flatten()
-			.map(|(k, v)| {
-				Some((
-					k.to_string(),
-					v.as_ref().map(|v| semver::Version::parse(v).ok()).flatten(),
-				))
-			})
-			.flatten()
-			.collect();
-		Self {
-			fin_version,
-			game_version,
-			check_mods,
-			mods,
-		}
-	}
+/// Links to the current query in each sort order.
+fn sort_options(query: Option<&str>, active: Sort) -> Vec<SortOption> {
+	let params: Vec<_> = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+		.filter(|(k, _)| k != "sort" && k != "page")
+		.collect();
+	Sort::ALL
+		.iter()
+		.map(|sort| {
+			let mut url = url::form_urlencoded::Serializer::new(String::new());
+			url.extend_pairs(params.iter());
+			url.append_pair("sort", &sort.to_string());
+			SortOption {
+				label: sort.to_string(),
+				active: *sort == active,
+				url: format!("/?{}", url.finish()),
+			}
+		})
+		.collect()
 }
 
-fn check_version(requirement: Option<&String>, version: Option<&semver::Version>) -> Option<bool> {
-	let requirement = semver::VersionReq::parse(requirement?).ok()?;
-	Some(requirement.matches(version?))
+#[derive(Deserialize)]
+pub struct Pagination {
+	page: Option<usize>,
+	page_size: Option<usize>,
+	/// The `next_cursor` of the previous page, takes precedence over the page.
+	cursor: Option<String>,
 }
 
-fn check_versions(query: &QueryVersions, version_data: &VersionData) -> Option<()> {
-	check_version(
-		version_data.fin_version.as_ref(),
-		query.fin_version.as_ref(),
-	)
-	.unwrap_or(true)
-	.then_some(())?;
-	check_version(
-		version_data.game_version.as_ref(),
-		query.game_version.as_ref(),
-	)
-	.unwrap_or(true)
-	.then_some(())?;
-	if query.check_mods {
-		for m in &version_data.mod_dependencies {
-			let version = query.mods.get(&m.id)?;
-			if let Some(requirement) = &m.version {
-				let version = version.as_ref()?;
-				let requirement = semver::VersionReq::parse(&requirement).ok()?;
-				if !requirement.matches(version) {
-					return None;
-				}
-			}
-		}
+fn parse_version(s: &str) -> Option<semver::Version> {
+	parse_version_lenient(s).ok().map(|v| v.value)
+}
+
+fn parse_search_query(s: &SearchQuery) -> QueryVersions {
+	let fin_version = s.fin_version.as_deref().and_then(parse_version);
+	let game_version = s
+		.game_version
+		.as_deref()
+		.and_then(|s| parse_game_version(s).ok());
+	let check_mods = s.check_mods.unwrap_or(false);
+	let mods = s
+		.other
+		.iter()
+		.map(|(k, v)| Some((k.strip_prefix("mod_")?, v)))
+		.flatten()
+		.map(|(k, v)| {
+			Some((
+				k.to_string(),
+				v.as_deref().and_then(parse_version),
+			))
+		})
+		.flatten()
+		.collect();
+	let channel = s
+		.channel
+		.as_deref()
+		.and_then(|s| s.parse().ok())
+		.unwrap_or_default();
+	QueryVersions {
+		fin_version,
+		game_version,
+		check_mods,
+		mods,
+		channel,
 	}
-	Some(())
 }
 
 pub async fn get_index(
@@ -119,10 +154,8 @@ pub async fn get_index(
 	State(repository): State<Repository>,
 	Query(query): Query<SearchQuery>,
 	Query(pagination): Query<Pagination>,
+	RawQuery(raw_query): RawQuery,
 ) -> axum::response::Result<Response> {
-	let searcher = repository.reader.searcher();
-	let schema = repository.package_schema.clone();
-
 	let search = htmx
 		.as_ref()
 		.map(|uri| Query::try_from_uri(uri).ok())
@@ -140,116 +173,127 @@ pub async fn get_index(
 		.map(|q| {
 			let mut q: Pagination = q.0;
 			q.page = pagination.page.clone().or(q.page);
+			q.cursor = pagination.cursor.clone().or(q.cursor);
 			q
 		})
 		.unwrap_or(pagination);
-	let search_versions = QueryVersions::parse_search_query(&search);
-
-	let query = search
-		.search
-		.as_deref()
-		.filter(|s| !s.is_empty())
-		.unwrap_or("*");
-
-	let query_parser = tantivy::query::QueryParser::for_index(
-		&repository.index,
-		vec![
-			schema.id,
-			schema.name,
-			schema.short_description,
-			schema.readme,
-			schema.tags,
-			schema.versions,
-			schema.authors,
-		],
+	let search_versions = parse_search_query(&search);
+	let facets = parse_facets(
+		htmx.as_ref()
+			.and_then(|uri| uri.query())
+			.or(raw_query.as_deref()),
 	);
 
-	let query = query_parser
-		.parse_query(&query)
-		.ok()
-		.unwrap_or(Box::new(query::AllQuery {}));
-
-	let page_size = pagination.page_size.unwrap_or(10);
-	let offset = pagination.page.unwrap_or(0) * page_size;
-
-	let collector = TopDocs::with_limit(page_size).and_offset(offset);
-	let collector_search_params = search_versions.clone();
-	let collector = BytesFilterCollector::new(
-		"version_data".to_string(),
-		move |bytes: &[u8]| {
-			if let Ok(version_data) = bitcode::decode::<index::VersionData>(bytes)
-				.map_err(|e| println!("Error at decoding Version Data: {e}"))
-			{
-				check_versions(&collector_search_params, &version_data) == Some(())
-			} else {
-				false
-			}
-		},
-		collector,
-	);
-
-	let top_docs = searcher.search(&query, &collector).ok().unwrap_or_default();
+	let sort = search
+		.sort
+		.as_deref()
+		.and_then(|s| s.parse().ok())
+		.unwrap_or_default();
+	let page_size = pagination.page_size.unwrap_or(10).clamp(1, MAX_PAGE_SIZE);
+	let cursor = pagination
+		.cursor
+		.as_deref()
+		.map(str::parse)
+		.transpose()
+		.map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+	let request = SearchRequest {
+		query: search.search.clone().unwrap_or_default(),
+		versions: search_versions,
+		provides: search.provides.clone(),
+		facets: facets
+			.iter()
+			.filter_map(|f| Facet::from_text(f).ok())
+			.collect(),
+		sort,
+		limit: page_size,
+		// saturates so huge pages hit the offset limit of the search instead of wrapping around
+		offset: pagination.page.unwrap_or(0).saturating_mul(page_size),
+		cursor,
+		lenient: !json_only || search.lenient.unwrap_or(false),
+		..Default::default()
+	};
+	let SearchResult {
+		hits,
+		total,
+		facets: facet_counts,
+		suggestion,
+		query_errors,
+		has_more,
+		next_cursor,
+	} = match repository.search(request).await {
+		Ok(result) => result,
+		Err(SearchError::Query(invalid)) => {
+			return Ok((
+				StatusCode::BAD_REQUEST,
+				Json(InvalidQueryJsonResponse { error: invalid }),
+			)
+				.into_response())
+		}
+		Err(e @ SearchError::Offset(_)) => return Err((StatusCode::BAD_REQUEST, e.to_string()).into()),
+		Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into()),
+	};
 
 	let packages: Vec<PackageCard> = join_all(
-		top_docs
-			.into_iter()
-			.map(|(_score, doc_address)| {
-				let doc: tantivy::TantivyDocument = searcher.doc(doc_address).ok()?;
-
-				let id = doc.get_first(schema.id)?.as_str()?.to_string();
-
-				let version = if search_versions.fin_version.is_some() || search_versions.game_version.is_some() || search_versions.check_mods {
-					let mut version: Vec<_> = doc.get_all(schema.version_data).zip(doc.get_all(schema.versions))
-						.map(|(data, version)| Some((data.as_bytes()?, version.as_str()?)))
-						.flatten()
-						.map(|(data, version)| {
-							Some((bitcode::decode::<VersionData>(data).ok()?, version))
-						})
-						.flatten()
-						.map(|(data, version)| {
-							check_versions(&search_versions, &data)?;
-							Some(version)
-						})
-						.flatten()
-						.map(|v| semver::Version::parse(v).ok())
-						.flatten()
-						.collect();
-					version.sort();
-					version.last().cloned()
-				} else {
-					None
-				};
-
-				let meta = repository.get_package_meta_by_id(id.clone());
-				Some(async move { Some((id, version, meta.await.ok()?)) })
-			})
-			.flatten(),
+		hits.into_iter().map(|hit| {
+			let meta = repository.get_package_meta_by_id(hit.id.clone());
+			async move { Some((hit.id, hit.version, hit.highlight, hit.eeproms, meta.await.ok()?)) }
+		}),
 	)
 	.await
 	.into_iter()
 	.flatten()
-	.map(|(id, version, meta)| PackageCard {
+	.map(|(id, version, highlight, eeproms, meta)| PackageCard {
 		id,
 		name: meta.name,
 		short_description: meta.short_description,
 		version,
+		deprecated: meta.deprecated,
+		highlight,
+		eeproms,
 	})
 	.collect();
 
 	if json_only {
-		Ok(Json(packages).into_response())
+		Ok(Json(ListPackageJsonResponse {
+			packages,
+			total,
+			facets: facet_counts,
+			suggestion,
+			query_errors,
+			has_more,
+			next_cursor: next_cursor.map(|c| c.to_string()),
+		})
+		.into_response())
 	} else {
 		let next_page = pagination.page.unwrap_or(0) + 1;
+		let next_cursor = next_cursor.map(|c| c.to_string());
+		// the suggestion and query errors are only shown above the first page
+		let suggestion = suggestion.filter(|_| next_page == 1);
+		let query_errors = query_errors.filter(|_| next_page == 1);
 
 		if htmx.is_some() {
 			Ok(ListPackageResponse {
 				packages,
 				next_page,
+				next_cursor,
+				suggestion,
+				query_errors,
 			}.into_response())
 		} else {
+			let query = raw_query.as_deref();
 			Ok(GetIndexResponse {
 				packages,
 				next_page,
+				next_cursor,
+				suggestion,
+				query_errors,
+				facets: vec![
+					facet_group("Tags", query, &facets, &facet_counts.tags),
+					facet_group("Authors", query, &facets, &facet_counts.authors),
+					facet_group("Required Mods", query, &facets, &facet_counts.mods),
+					facet_group("Minimum FIN Version", query, &facets, &facet_counts.fin_versions),
+				],
+				sorts: sort_options(query, sort),
 			}.into_response())
 		}
 	}
@@ -1,29 +1,117 @@
 use crate::repository::Repository;
 use crate::routes::middleware::{AcceptJsonOnly, HTMXExtension};
-use crate::templates::package::{GetPackageResponse, ListPackageResponse};
+use crate::routes::{parse_search_query, SearchQuery};
+use crate::templates::package::{Alternatives, GetPackageResponse, ListPackageResponse, ResolvedDependency};
+use crate::templates::PackageCard;
 use crate::util::{read_file_or_url, URLOrFile};
 use axum::extract::{Path, Query, State};
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response, Result};
 use axum::{http, Extension, Json};
+use ficsit_networks_repository::compat::{Channel, QueryVersions};
 use ficsit_networks_repository::index;
 use ficsit_networks_repository::index::VersionData;
+use ficsit_networks_repository::resolve::ResolvedPackage;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
 use tantivy::collector::{BytesFilterCollector, TopDocs};
 use tantivy::schema::Value;
 use ficsit_networks_repository::model::{Package, Version};
+use ficsit_networks_repository::util::parse_version_lenient;
+use futures_util::future::join_all;
 
 #[derive(Deserialize)]
 pub struct PackageQuery {
 	version: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct EepromQuery {
+	version: Option<String>,
+	channel: Option<String>,
+	/// `1` to download the code minified by the indexer.
+	minified: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct PackageJsonResponse {
 	pub package: Package,
 	pub version: Option<Version>,
+	pub alternatives: Vec<Alternatives>,
+	pub related: Vec<PackageCard>,
+	pub dependencies: Vec<ResolvedDependency>,
+	pub dependency_error: Option<String>,
+}
+
+/// The number of related packages shown on the package page.
+const RELATED_PACKAGES: usize = 5;
+
+/// Packages similar to the given one, compatible with the FIN and game versions of the query.
+async fn get_related(repository: &Repository, id: &str, versions: &QueryVersions) -> Result<Vec<PackageCard>> {
+	let hits = repository
+		.related(id.to_string(), versions.clone(), RELATED_PACKAGES)
+		.await
+		.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+	Ok(join_all(hits.into_iter().map(|hit| async move {
+		let meta = repository.get_package_meta_by_id(hit.id.clone()).await.ok()?;
+		Some(PackageCard {
+			id: hit.id,
+			name: meta.name,
+			short_description: meta.short_description,
+			version: hit.version,
+			deprecated: meta.deprecated,
+			highlight: None,
+			eeproms: Vec::new(),
+		})
+	}))
+	.await
+	.into_iter()
+	.flatten()
+	.collect())
+}
+
+/// The resolved dependencies without the package they got resolved for.
+fn get_dependencies(resolved: Vec<ResolvedPackage>) -> Vec<ResolvedDependency> {
+	resolved
+		.into_iter()
+		.skip(1)
+		.map(|p| ResolvedDependency {
+			id: p.id,
+			version: p.version.to_string(),
+			required_by: p.required_by,
+		})
+		.collect()
+}
+
+async fn get_alternatives(repository: &Repository, id: &str, capability: &str) -> Alternatives {
+	let packages = join_all(
+		repository
+			.get_packages_providing(capability.to_string())
+			.await
+			.into_iter()
+			.filter(|other| other != id)
+			.map(|id| async move {
+				let meta = repository.get_package_meta_by_id(id.clone()).await.ok()?;
+				Some(PackageCard {
+					id,
+					name: meta.name,
+					short_description: meta.short_description,
+					version: None,
+					deprecated: meta.deprecated,
+					highlight: None,
+					eeproms: Vec::new(),
+				})
+			}),
+	)
+	.await
+	.into_iter()
+	.flatten()
+	.collect();
+	Alternatives {
+		capability: capability.to_string(),
+		packages,
+	}
 }
 
 pub async fn get_package(
@@ -31,29 +119,126 @@ pub async fn get_package(
 	State(repository): State<Repository>,
 	Path((package_id)): Path<(String)>,
 	Query(query): Query<PackageQuery>,
+	Query(search_query): Query<SearchQuery>,
 ) -> Result<Response> {
 	let package = repository.get_package_by_id(&package_id).await?;
 
-	let version = query
-		.version
-		.map(|v| {
-			let version = semver::Version::parse(&v).ok()?;
-			package.versions.iter().find(|v| v.version == version)
-		})
-		.flatten()
-		.or(package.versions.first())
-		.cloned();
+	let versions = parse_search_query(&search_query);
+	let latest = repository
+		.get_latest_compatible_version(package_id.clone(), versions.clone())
+		.await;
+
+	let version = match query.version.as_deref() {
+		Some(version) => {
+			let version = parse_version_lenient(version)
+				.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+				.value;
+			let version = package.versions.iter().find(|v| v.version == version);
+			Some(version.ok_or(StatusCode::NOT_FOUND)?)
+		}
+		None => latest
+			.and_then(|version| package.versions.iter().find(|v| v.version == version))
+			.or_else(|| {
+				package
+					.versions
+					.iter()
+					.filter(|v| !v.yanked && v.channel == Channel::Stable)
+					.max_by(|v1, v2| v1.version.cmp(&v2.version))
+			}),
+	}
+	.cloned();
+
+	let alternatives = join_all(
+		version
+			.iter()
+			.flat_map(|v| v.provides.iter())
+			.map(|capability| get_alternatives(&repository, &package.id, capability)),
+	)
+	.await;
+
+	let related = get_related(&repository, &package.id, &versions).await?;
+
+	let (dependencies, dependency_error) = match &version {
+		Some(v) if !v.yanked && !v.package_dependencies.is_empty() => {
+			let query = QueryVersions {
+				channel: versions.channel.max(v.channel),
+				..versions
+			};
+			match repository.resolve_dependencies(package.id.clone(), v.version.clone(), query).await {
+				Ok(resolved) => (get_dependencies(resolved), None),
+				Err(e) => (Vec::new(), Some(e)),
+			}
+		}
+		_ => (Vec::new(), None),
+	};
 
 	if json_only {
 		Ok(Json(PackageJsonResponse{
 			package,
-			version
+			version,
+			alternatives,
+			related,
+			dependencies,
+			dependency_error,
 		}).into_response())
 	} else {
 		Ok(GetPackageResponse {
 			package,
 			version,
+			alternatives,
+			related,
+			dependencies,
+			dependency_error,
 		}
 			.into_response())
 	}
 }
+
+/// Downloads the code of an EEPROM of the given or latest version of the package.
+pub async fn get_eeprom(
+	State(repository): State<Repository>,
+	Path((package_id, file)): Path<(String, String)>,
+	Query(query): Query<EepromQuery>,
+) -> Result<Response> {
+	let version = match query.version.as_deref() {
+		Some(version) => Some(
+			parse_version_lenient(version)
+				.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+				.value,
+		),
+		None => {
+			let channel = query
+				.channel
+				.as_deref()
+				.and_then(|c| c.parse().ok())
+				.unwrap_or_default();
+			repository
+				.get_latest_compatible_version(
+					package_id.clone(),
+					QueryVersions {
+						channel,
+						..Default::default()
+					},
+				)
+				.await
+		}
+	}
+	.ok_or(StatusCode::NOT_FOUND)?;
+	let minified = matches!(query.minified.as_deref(), Some("1" | "true"));
+
+	let code = repository
+		.get_eeprom_code(package_id, version, file.clone(), minified)
+		.await
+		.ok_or(StatusCode::NOT_FOUND)?;
+	Ok((
+		[
+			(http::header::CONTENT_TYPE, "text/x-lua; charset=utf-8".to_string()),
+			(
+				http::header::CONTENT_DISPOSITION,
+				format!("attachment; filename=\"{}\"", file.replace('"', "")),
+			),
+		],
+		code,
+	)
+		.into_response())
+}
@@ -0,0 +1,32 @@
+use crate::repository::Repository;
+use crate::routes::middleware::AcceptJsonOnly;
+use crate::templates::SuggestResponse;
+use askama_axum::IntoResponse;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+	q: Option<String>,
+	limit: Option<usize>,
+}
+
+pub async fn get_suggest(
+	Extension(AcceptJsonOnly(json_only)): Extension<AcceptJsonOnly>,
+	State(repository): State<Repository>,
+	Query(query): Query<SuggestQuery>,
+) -> axum::response::Result<Response> {
+	let completions = repository
+		.complete(query.q.unwrap_or_default(), query.limit.unwrap_or(10).min(50))
+		.await
+		.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+	if json_only {
+		Ok(Json(completions).into_response())
+	} else {
+		Ok(SuggestResponse { completions }.into_response())
+	}
+}
@@ -0,0 +1,107 @@
+use crate::repository::Repository;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response, Result};
+use axum::Json;
+use ficsit_networks_repository::compat::{Channel, QueryVersions};
+use ficsit_networks_repository::game::parse_game_version;
+use ficsit_networks_repository::util::parse_version_lenient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The maximum number of packages a single update check can contain.
+pub const MAX_PACKAGES: usize = 500;
+
+#[derive(Deserialize)]
+pub struct InstalledPackage {
+	id: String,
+	version: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCheckRequest {
+	fin_version: Option<String>,
+	game_version: Option<String>,
+	#[serde(default)]
+	channel: Channel,
+	/// The installed mods and their versions, mod dependencies are only checked if this is set.
+	mods: Option<HashMap<String, Option<String>>>,
+	packages: Vec<InstalledPackage>,
+}
+
+#[derive(Serialize)]
+pub struct UpdateCheckEntry {
+	pub id: String,
+	pub installed: String,
+	/// Whether the package does not exist in the repository, all other fields are empty then.
+	pub unknown: bool,
+	/// The newest version compatible with the clients environment, [None] if there is none.
+	pub latest: Option<String>,
+	pub upgrade: bool,
+	/// Whether the installed version got yanked and should be replaced.
+	pub yanked: bool,
+	pub yanked_reason: Option<String>,
+}
+
+type BadRequest = (StatusCode, String);
+
+fn parse_version(version: &str) -> std::result::Result<semver::Version, BadRequest> {
+	parse_version_lenient(version)
+		.map(|v| v.value)
+		.map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid version '{version}': {e}")))
+}
+
+fn parse_version_opt(version: &Option<String>) -> std::result::Result<Option<semver::Version>, BadRequest> {
+	version.as_deref().map(parse_version).transpose()
+}
+
+pub async fn post_updates(
+	State(repository): State<Repository>,
+	Json(request): Json<UpdateCheckRequest>,
+) -> Result<Response> {
+	if request.packages.len() > MAX_PACKAGES {
+		return Err((
+			StatusCode::BAD_REQUEST,
+			format!("Too many packages, at most {MAX_PACKAGES} can be checked at once"),
+		)
+			.into());
+	}
+	let query = QueryVersions {
+		fin_version: parse_version_opt(&request.fin_version)?,
+		game_version: request
+			.game_version
+			.as_deref()
+			.map(|v| parse_game_version(v).map_err(|e| (StatusCode::BAD_REQUEST, e)))
+			.transpose()?,
+		check_mods: request.mods.is_some(),
+		channel: request.channel,
+		mods: request
+			.mods
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(id, version)| Ok((id, parse_version_opt(&version)?)))
+			.collect::<std::result::Result<_, BadRequest>>()?,
+	};
+
+	let installed = request
+		.packages
+		.into_iter()
+		.map(|package| Ok((package.id, parse_version(&package.version)?)))
+		.collect::<std::result::Result<Vec<_>, BadRequest>>()?;
+	let updates = repository.check_updates(installed.clone(), query).await;
+	let entries: Vec<_> = installed
+		.into_iter()
+		.zip(updates)
+		.map(|((id, installed), update)| UpdateCheckEntry {
+			unknown: update.is_none(),
+			latest: update.as_ref().and_then(|u| u.latest.as_ref()).map(|v| v.to_string()),
+			upgrade: update.as_ref().is_some_and(|u| u.upgrade),
+			yanked: update.as_ref().is_some_and(|u| u.yanked),
+			yanked_reason: update.and_then(|u| u.yanked_reason),
+			installed: installed.to_string(),
+			id,
+		})
+		.collect();
+
+	Ok(Json(entries).into_response())
+}
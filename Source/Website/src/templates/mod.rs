@@ -1,6 +1,7 @@
 pub mod package;
 
 use askama::Template;
+use ficsit_networks_repository::search::{Completion, EepromHit, Highlight, InvalidQuery, SearchFacets};
 use ficsit_networks_repository::util;
 use itertools::Itertools;
 use serde::Serialize;
@@ -12,17 +13,73 @@ pub struct PackageCard {
 	pub short_description: String,
 	#[serde(serialize_with="util::serialize_semver_opt")]
 	pub version: Option<semver::Version>,
+	pub deprecated: bool,
+	/// Where the search matched the description or readme.
+	pub highlight: Option<Highlight>,
+	/// The EEPROMs matching the search.
+	pub eeproms: Vec<EepromHit>,
 }
 
 #[derive(Template)]
 #[template(source = "", ext = "")]
 pub struct EmptyResponse {}
 
+#[derive(Serialize)]
+pub struct ListPackageJsonResponse {
+	pub packages: Vec<PackageCard>,
+	/// The number of all matching packages, not only of this page.
+	pub total: usize,
+	pub facets: SearchFacets,
+	pub suggestion: Option<String>,
+	/// The problems of the query that got ignored, only for lenient searches.
+	pub query_errors: Option<InvalidQuery>,
+	/// Whether there are more packages after this page.
+	pub has_more: bool,
+	/// Pass as `cursor` to get the next page.
+	pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct InvalidQueryJsonResponse {
+	pub error: InvalidQuery,
+}
+
+pub struct FacetChip {
+	pub label: String,
+	pub count: usize,
+	pub active: bool,
+	/// The package list with this facet toggled.
+	pub url: String,
+}
+
+pub struct SortOption {
+	pub label: String,
+	pub active: bool,
+	pub url: String,
+}
+
+pub struct FacetGroup {
+	pub name: String,
+	pub chips: Vec<FacetChip>,
+}
+
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct GetIndexResponse {
 	pub packages: Vec<PackageCard>,
 	pub next_page: usize,
+	/// The cursor of the next page, [None] on the last page.
+	pub next_cursor: Option<String>,
+	pub suggestion: Option<String>,
+	pub query_errors: Option<InvalidQuery>,
+	pub facets: Vec<FacetGroup>,
+	pub sorts: Vec<SortOption>,
+}
+
+#[derive(Template)]
+#[template(path = "suggest.html")]
+pub struct SuggestResponse {
+	pub completions: Vec<Completion>,
 }
 
 #[derive(Template)]
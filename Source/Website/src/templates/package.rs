@@ -1,14 +1,22 @@
 use crate::templates::PackageCard;
 use askama::Template;
+use ficsit_networks_repository::compat::Channel;
+use ficsit_networks_repository::lua::Severity;
 use ficsit_networks_repository::metadata;
 use ficsit_networks_repository::model::*;
+use ficsit_networks_repository::search::InvalidQuery;
 use itertools::Itertools;
+use serde::Serialize;
 
 #[derive(Template)]
 #[template(path = "package/list.html")]
 pub struct ListPackageResponse {
 	pub packages: Vec<PackageCard>,
 	pub next_page: usize,
+	/// The cursor of the next page, [None] on the last page.
+	pub next_cursor: Option<String>,
+	pub suggestion: Option<String>,
+	pub query_errors: Option<InvalidQuery>,
 }
 
 #[derive(Template)]
@@ -16,4 +24,25 @@ pub struct ListPackageResponse {
 pub struct GetPackageResponse {
 	pub package: Package,
 	pub version: Option<Version>,
+	pub alternatives: Vec<Alternatives>,
+	/// Packages with similar readmes, descriptions and tags.
+	pub related: Vec<PackageCard>,
+	pub dependencies: Vec<ResolvedDependency>,
+	/// Why the dependencies of the version can't be resolved.
+	pub dependency_error: Option<String>,
+}
+
+/// A package version the dependencies of the shown version resolve to.
+#[derive(Clone, Serialize)]
+pub struct ResolvedDependency {
+	pub id: String,
+	pub version: String,
+	pub required_by: Option<String>,
+}
+
+/// Other packages that provide the same capability as the shown package.
+#[derive(Clone, Serialize)]
+pub struct Alternatives {
+	pub capability: String,
+	pub packages: Vec<PackageCard>,
 }